@@ -0,0 +1,82 @@
+#!/usr/bin/env python3
+"""Minimal Tetris Bot Protocol bot, to try out (and debug) the tbp agent.
+
+It keeps track of the board and the queue, and suggests every
+drop of the current piece in its north orientation, lowest first.
+
+    tetrus --agent tbp --bot-command "python3 scripts/tbp_example_bot.py"
+"""
+import json
+import sys
+
+WIDTH = 10
+HEIGHT = 40
+
+NORTH_CELLS = {
+    "I": [(-1, 0), (0, 0), (1, 0), (2, 0)],
+    "O": [(0, 0), (1, 0), (0, 1), (1, 1)],
+    "T": [(-1, 0), (0, 0), (1, 0), (0, 1)],
+    "L": [(-1, 0), (0, 0), (1, 0), (1, 1)],
+    "J": [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+    "S": [(-1, 0), (0, 0), (0, 1), (1, 1)],
+    "Z": [(-1, 1), (0, 1), (0, 0), (1, 0)],
+}
+
+
+def send(message):
+    print(json.dumps(message), flush=True)
+
+
+def fits(board, cells):
+    return all(0 <= x < WIDTH and 0 <= y < HEIGHT and board[y][x] is None for x, y in cells)
+
+
+def place(board, cells):
+    for x, y in cells:
+        board[y][x] = "G"
+    kept = [row for row in board if any(cell is None for cell in row)]
+    return kept + [[None] * WIDTH for _ in range(HEIGHT - len(kept))]
+
+
+def drops(board, piece):
+    # lowest resting position of the piece for every column of its center
+    for x in range(WIDTH):
+        y = HEIGHT - 2
+        cells = [(x + dx, y + dy) for dx, dy in NORTH_CELLS[piece]]
+        if not fits(board, cells):
+            continue
+        while fits(board, [(cx, cy - 1) for cx, cy in cells]):
+            cells = [(cx, cy - 1) for cx, cy in cells]
+            y -= 1
+        yield y, {"location": {"type": piece, "orientation": "north", "x": x, "y": y}, "spin": "none"}
+
+
+def main():
+    board = [[None] * WIDTH for _ in range(HEIGHT)]
+    queue = []
+    send({"type": "info", "name": "example", "version": "0.1", "author": "tetrus", "features": []})
+    for line in sys.stdin:
+        message = json.loads(line)
+        kind = message["type"]
+        if kind == "rules":
+            send({"type": "ready"})
+        elif kind == "start":
+            board = message["board"]
+            queue = message["queue"]
+        elif kind == "new_piece":
+            queue.append(message["piece"])
+        elif kind == "suggest":
+            moves = sorted(drops(board, queue[0]), key=lambda drop: drop[0])
+            send({"type": "suggestion", "moves": [move for _, move in moves]})
+        elif kind == "play":
+            location = message["move"]["location"]
+            board = place(board, [
+                (location["x"] + dx, location["y"] + dy) for dx, dy in NORTH_CELLS[location["type"]]
+            ])
+            queue.pop(0)
+        elif kind == "quit":
+            break
+
+
+if __name__ == "__main__":
+    main()
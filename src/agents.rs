@@ -0,0 +1,111 @@
+use rand::Rng;
+
+use crate::{
+    ai::{SearchConfig, TetrisBot},
+    grid::Grid,
+    moves::{Move, SimpleMove},
+    score::Score,
+    shapes::ShapePosition,
+    tbp::TbpAgent,
+};
+
+// What an agent gets to see of the game, each time a new piece spawns.
+// The grid still contains the current piece.
+pub struct Observation<'a> {
+    pub grid: &'a Grid,
+    pub current_shape: &'a ShapePosition,
+    // upcoming pieces, next one first
+    pub queue: &'a [ShapePosition],
+    // the game has no hold mechanic yet, so this is always empty for now
+    pub hold: Option<&'a ShapePosition>,
+    pub score: &'a Score,
+}
+
+// Something that plays the game in place of a human
+pub trait Agent {
+    // Call this function each time a new shape is presented
+    fn update_policy(&mut self, observation: &Observation);
+
+    // next input to play for the current shape, if any
+    fn pop_next_move(&mut self) -> Option<Move>;
+
+    // shown in the high scores
+    fn name(&self) -> String;
+}
+
+pub const AGENT_NAMES: [&str; 3] = ["heuristic", "random", "tbp"];
+
+// settings for the agents that need some
+pub struct AgentConfig {
+    pub search_config: SearchConfig,
+    // command that launches the external bot used by the "tbp" agent
+    pub bot_command: Option<String>,
+}
+
+pub fn agent_from_name(name: &str, config: &AgentConfig) -> Result<Box<dyn Agent>, String> {
+    return match name {
+        "heuristic" => Ok(Box::new(TetrisBot::new(config.search_config))),
+        "random" => Ok(Box::new(RandomAgent::new())),
+        "tbp" => {
+            let command = config
+                .bot_command
+                .as_ref()
+                .ok_or("the tbp agent needs a bot command")?;
+            match TbpAgent::new(command) {
+                Ok(agent) => Ok(Box::new(agent)),
+                Err(err) => Err(format!("could not start TBP bot '{}': {}", command, err)),
+            }
+        }
+        _ => Err(format!(
+            "unknown agent '{}', expected one of {:?}",
+            name, AGENT_NAMES
+        )),
+    };
+}
+
+// Baseline that mashes random keys before hard-dropping each piece
+pub struct RandomAgent {
+    moves: Vec<Move>,
+}
+
+impl RandomAgent {
+    const MAX_MOVES_PER_PIECE: usize = 10;
+    const SIMPLE_MOVES: [SimpleMove; 5] = [
+        SimpleMove::Left,
+        SimpleMove::Right,
+        SimpleMove::Down,
+        SimpleMove::Rotate,
+        SimpleMove::RotateCounterClockwise,
+    ];
+
+    pub fn new() -> Self {
+        return RandomAgent { moves: Vec::new() };
+    }
+}
+
+impl Default for RandomAgent {
+    fn default() -> Self {
+        return RandomAgent::new();
+    }
+}
+
+impl Agent for RandomAgent {
+    fn update_policy(&mut self, _observation: &Observation) {
+        let mut rng = rand::thread_rng();
+        let n_moves = rng.gen_range(0..=Self::MAX_MOVES_PER_PIECE);
+        // the hard-drop goes first because moves are popped from the end
+        self.moves = vec![Move::HardDrop];
+        for _ in 0..n_moves {
+            let move_ = Self::SIMPLE_MOVES[rng.gen_range(0..Self::SIMPLE_MOVES.len())];
+            self.moves.push(Move::Simple(move_));
+        }
+    }
+
+    fn pop_next_move(&mut self) -> Option<Move> {
+        return self.moves.pop();
+    }
+
+    fn name(&self) -> String {
+        return "random".to_string();
+    }
+}
@@ -1,78 +1,107 @@
-use std::iter;
+use std::{
+    iter,
+    time::{Duration, Instant},
+};
 
 use ordered_float::OrderedFloat;
+use rayon::prelude::*;
 
 use crate::{
-    constants::{GRID_HEIGHT, GRID_WIDTH},
-    grid::{Grid, EMPTY_CELL},
+    agents::{Agent, Observation},
+    bitboard::{BitBoard, FULL_ROW},
+    constants::GRID_HEIGHT,
+    grid::Grid,
     moves::{Move, SimpleMove},
-    shapes::ShapePosition,
+    placements::{enumerate_placements, Placement},
+    shapes::{ShapePosition, N_SHAPES},
     stats::variance,
 };
 
+#[derive(Clone, Copy, Debug)]
+pub struct SearchConfig {
+    // how many preview pieces the search takes into account (0 = greedy)
+    pub lookahead: usize,
+    // how many candidate boards are kept at each ply
+    pub beam_width: usize,
+    // once the preview is exhausted, average over every possible next piece
+    pub expect_unknown_piece: bool,
+    // the search stops going deeper once this much time has been spent, if set
+    // (which makes the choice depend on the speed of the machine)
+    pub time_budget: Option<Duration>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        return SearchConfig {
+            lookahead: 1,
+            beam_width: 5,
+            expect_unknown_piece: false,
+            time_budget: None,
+        };
+    }
+}
+
 pub struct TetrisBot {
     moves: Vec<Move>,
+    config: SearchConfig,
+}
+
+// a board reached after placing the current piece and some preview pieces
+struct SearchNode {
+    board: BitBoard,
+    // index of the placement of the *current* piece this branch started with
+    root_index: usize,
+    lines_cleared: i32,
+    score: f32,
 }
 
-struct GridAnalysis {}
+// score given to a board on which the next piece cannot even spawn
+const TOP_OUT_SCORE: f32 = -1000.;
+
+// features of a board, used to score it
+pub struct GridAnalysis {}
 
 impl GridAnalysis {
-    fn get_first_nonempty_row_index(grid: &Grid) -> usize {
-        return grid
-            .grid
-            .iter()
-            .enumerate()
-            .filter(|(_i, row)| row.iter().any(|&x| x != EMPTY_CELL))
-            .map(|(i_row, _row)| i_row)
-            .next()
-            .unwrap_or(GRID_HEIGHT as usize);
+    pub fn get_first_nonempty_row_index(board: &BitBoard) -> usize {
+        let max_height = board.column_heights().iter().max().unwrap_or(&0);
+        return GRID_HEIGHT as usize - *max_height as usize;
     }
 
-    fn count_filled_rows(grid: &Grid) -> usize {
-        return grid
-            .grid
+    pub fn count_filled_rows(board: &BitBoard) -> usize {
+        return board.rows().iter().filter(|&&row| row == FULL_ROW).count();
+    }
+
+    pub fn col_height_variance(board: &BitBoard) -> f32 {
+        // variance of column height (empty columns are left out)
+        let col_heights: Vec<i32> = board
+            .column_heights()
             .iter()
-            .filter(|row| row.iter().all(|&x| x != EMPTY_CELL))
-            .count();
-    }
-
-    fn col_height_variance(grid: &Grid) -> f32 {
-        // variance of column height
-        let mut col_heights: Vec<i32> = Vec::new();
-        for col_index in 0..GRID_WIDTH as usize {
-            for row_index in 0..(GRID_HEIGHT as usize) {
-                if grid.grid[row_index][col_index] != EMPTY_CELL {
-                    col_heights.push(GRID_HEIGHT as i32 - row_index as i32);
-                    break;
-                }
-            }
-        }
+            .filter(|&&height| height > 0)
+            .map(|&height| height as i32)
+            .collect();
         return variance(&col_heights);
     }
 
-    fn count_gaps(grid: &Grid) -> usize {
+    pub fn count_gaps(board: &BitBoard) -> usize {
         // a gap is when there are empty cells with filled cells above them
-        // we calculate this metric per *column*.
+        // we calculate this metric per *column*, for all columns at once:
+        // `covered` has a bit set for every column with a filled cell higher up
+        let mut covered: u16 = 0;
         let mut total_gap_count = 0;
-        for col_index in 0..GRID_WIDTH as usize {
-            let mut n_zeros = 0;
-            for row_index in (0..GRID_HEIGHT as usize).rev() {
-                if n_zeros > 0 && grid.grid[row_index][col_index] != EMPTY_CELL {
-                    total_gap_count += n_zeros;
-                    n_zeros = 0;
-                }
-                if grid.grid[row_index][col_index] == EMPTY_CELL {
-                    n_zeros += 1;
-                }
-            }
+        for &row in board.rows() {
+            total_gap_count += (covered & !row).count_ones() as usize;
+            covered |= row;
         }
         return total_gap_count;
     }
 }
 
 impl TetrisBot {
-    pub fn new() -> Self {
-        TetrisBot { moves: Vec::new() }
+    pub fn new(config: SearchConfig) -> Self {
+        TetrisBot {
+            moves: Vec::new(),
+            config,
+        }
     }
 
     // for debugging
@@ -87,6 +116,7 @@ impl TetrisBot {
                         Move::Simple(SimpleMove::Left) => "L",
                         Move::Simple(SimpleMove::Right) => "R",
                         Move::Simple(SimpleMove::Rotate) => "S",
+                        Move::Simple(SimpleMove::RotateCounterClockwise) => "s",
                     })
                     .rev(),
             )
@@ -95,107 +125,155 @@ impl TetrisBot {
             .join("");
     }
 
-    fn as_moves_sequence(
-        is_left: bool,
-        n_horizontal_moves: i16,
-        n_rotations: usize,
-        n_drops: usize,
-    ) -> Vec<Move> {
-        // rotate before shift because sometimes the shift puts you into a position
-        // where you can't rotate
-        return (0..n_drops)
-            .map(|_| Move::Simple(SimpleMove::Down))
-            .chain((0..n_rotations).map(|_| Move::Simple(SimpleMove::Rotate)))
-            .chain((0..n_horizontal_moves).map(|_| {
-                if is_left {
-                    Move::Simple(SimpleMove::Left)
-                } else {
-                    Move::Simple(SimpleMove::Right)
-                }
-            }))
-            .chain(iter::once(Move::HardDrop))
-            .rev() // because we use "pop" to fetch next move, which pops the last element of the vector
+    fn grid_score(grid: &BitBoard) -> f32 {
+        // highly reward full lines
+        // penalize height increase and gap increase
+        let index_of_first_nonempty_row = GridAnalysis::get_first_nonempty_row_index(grid) as i32;
+        let n_filled_rows = GridAnalysis::count_filled_rows(grid) as i32;
+        let n_gaps = GridAnalysis::count_gaps(grid) as i32;
+        return (n_filled_rows * 10 + index_of_first_nonempty_row - n_gaps) as f32;
+    }
+
+    // Score of the board obtained by locking `placement` on the node's board.
+    // This only reads the node, so that placements can be scored concurrently.
+    fn placement_score(node: &SearchNode, placement: &Placement) -> f32 {
+        let mut board = node.board;
+        board.set_pixels(&placement.shape.get_pixels());
+        return node.lines_cleared as f32 * 10. + Self::grid_score(&board);
+    }
+
+    // Score every placement of `shape` on the node's board, and only build the
+    // resulting boards for the `beam_width` best ones.
+    fn expand(
+        node: &SearchNode,
+        shape: &ShapePosition,
+        root_index: Option<usize>,
+        beam_width: usize,
+    ) -> Vec<SearchNode> {
+        // `collect` keeps the placement order, whatever the number of threads
+        let mut scored: Vec<(usize, f32, Placement)> = enumerate_placements(&node.board, shape)
+            .into_par_iter()
+            .enumerate()
+            .map(|(i, placement)| (i, Self::placement_score(node, &placement), placement))
+            .collect();
+        // stable sort, so that ties are broken by placement order
+        scored.sort_by_key(|(_i, score, _placement)| OrderedFloat(-score));
+        scored.truncate(beam_width);
+
+        return scored
+            .into_iter()
+            .map(|(i, score, placement)| {
+                let mut board = node.board;
+                board.set_pixels(&placement.shape.get_pixels());
+                let n_cleared = board.clear_completed_rows();
+                return SearchNode {
+                    board,
+                    root_index: root_index.unwrap_or(i),
+                    lines_cleared: node.lines_cleared + n_cleared,
+                    score,
+                };
+            })
             .collect();
     }
 
-    fn enumerate_options(
-        grid: &Grid,
-        original_shape: &ShapePosition,
-    ) -> Vec<(ShapePosition, Vec<Move>)> {
-        let mut result = Vec::new();
-        let n_drops = 1; // let the piece drop by 1 cell so we are free to rotate it
-
-        // these operations are not necessarily commutative?
-        // eg sometimes you can't move then rotate, but you can rotate then move
-        // but we can restrict ourselves to a subset of all possible moves for now
-
-        for direction in [SimpleMove::Left, SimpleMove::Right] {
-            for i_rotation in 0..(original_shape.n_rotations()) {
-                for i_shift in 0..(GRID_WIDTH / 2 + 3) {
-                    let direction_sign = if direction == SimpleMove::Left { -1 } else { 1 };
-                    // always add a vertical shift to allow pieces to rotate
-                    let shape =
-                        original_shape.n_moves(i_shift * direction_sign, i_rotation, n_drops);
-                    if grid.can_set_pixels(&shape.get_pixels(), &original_shape.get_pixels()) {
-                        let pos_after_fall = Self::get_position_after_fall(&shape, grid);
-                        let moves = Self::as_moves_sequence(
-                            direction == SimpleMove::Left,
-                            i_shift,
-                            i_rotation,
-                            n_drops as usize,
-                        );
-                        result.push((pos_after_fall, moves));
-                    }
-                }
-            }
-        }
-        return result;
+    fn expected_score_over_unknown_piece(node: &SearchNode) -> f32 {
+        let scores: Vec<f32> = (0..N_SHAPES)
+            .into_par_iter()
+            .map(|shape_index| {
+                Self::expand(node, &ShapePosition::with_shape(shape_index), None, 1)
+                    .first()
+                    .map_or(TOP_OUT_SCORE, |child| child.score)
+            })
+            .collect();
+        // summed sequentially: a parallel float sum depends on how work is split
+        return scores.iter().sum::<f32>() / N_SHAPES as f32;
     }
 
-    fn get_position_after_fall(original_shape: &ShapePosition, grid: &Grid) -> ShapePosition {
-        let mut shape = original_shape.clone();
-        loop {
-            if !grid.can_set_pixels(
-                &shape.moved_to(&SimpleMove::Down).get_pixels(),
-                &original_shape.get_pixels(),
-            ) {
-                return shape;
+    // Beam search over the current piece followed by the preview pieces.
+    // Returns the index of the best placement of the current piece, in the order of
+    // `enumerate_placements(board, current_shape)` (None if the piece cannot be placed).
+    // Branches are scored in parallel, but the choice does not depend on the
+    // number of threads (unless a time budget is set, and reached).
+    pub fn choose_placement(
+        board: &BitBoard,
+        current_shape: &ShapePosition,
+        preview: &[ShapePosition],
+        config: &SearchConfig,
+    ) -> Option<usize> {
+        let start_time = Instant::now();
+        let is_out_of_time = || {
+            config
+                .time_budget
+                .is_some_and(|time_budget| start_time.elapsed() >= time_budget)
+        };
+        let root = SearchNode {
+            board: *board,
+            root_index: 0,
+            lines_cleared: 0,
+            score: 0.,
+        };
+        let mut beam: Vec<SearchNode> = Self::expand(&root, current_shape, None, config.beam_width);
+
+        let n_plies = usize::min(config.lookahead, preview.len());
+        for shape in &preview[..n_plies] {
+            if is_out_of_time() {
+                break;
             }
-            shape = shape.moved_to(&SimpleMove::Down);
+            let mut next_beam: Vec<SearchNode> = beam
+                .par_iter()
+                .flat_map_iter(|node| {
+                    Self::expand(node, shape, Some(node.root_index), config.beam_width)
+                })
+                .collect();
+            if next_beam.is_empty() {
+                // every branch tops out: stick with the shallower search
+                break;
+            }
+            next_beam.sort_by_key(|node| OrderedFloat(-node.score));
+            next_beam.truncate(config.beam_width);
+            beam = next_beam;
         }
-    }
 
-    fn grid_score(grid: &Grid) -> f32 {
-        // highly reward full lines
-        // penalize height increase and gap increase
-        let index_of_first_nonempty_row = GridAnalysis::get_first_nonempty_row_index(grid) as i32;
-        let n_filled_rows = GridAnalysis::count_filled_rows(grid) as i32;
-        let n_gaps = GridAnalysis::count_gaps(grid) as i32;
-        return (n_filled_rows * 10 + index_of_first_nonempty_row - n_gaps) as f32;
+        if config.expect_unknown_piece && !is_out_of_time() {
+            beam.par_iter_mut().for_each(|node| {
+                node.score = Self::expected_score_over_unknown_piece(node);
+            });
+        }
+
+        beam.sort_by_key(|node| OrderedFloat(-node.score));
+        return beam.first().map(|best| best.root_index);
     }
 
-    fn decide_moves(original_grid: &Grid, current_shape: &ShapePosition) -> Vec<Move> {
-        // enumerate all possible positions reachable from current state
-        let mut grid = original_grid.clone();
-        grid.unset_pixels(&current_shape.get_pixels());
+    fn decide_moves(
+        original_grid: &Grid,
+        current_shape: &ShapePosition,
+        preview: &[ShapePosition],
+        config: &SearchConfig,
+    ) -> Vec<Move> {
+        let mut board = original_grid.bitboard();
+        board.unset_pixels(&current_shape.get_pixels());
 
-        if let Some((_, best_moves)) = Self::enumerate_options(&grid, current_shape)
-            .iter()
-            .max_by_key(|(shape, _moves)| {
-                grid.set_pixels(&shape.get_pixels(), 1);
-                let score = Self::grid_score(&grid);
-                grid.unset_pixels(&shape.get_pixels());
-                return OrderedFloat(score);
-            })
-        {
-            return best_moves.to_vec();
+        if let Some(index) = Self::choose_placement(&board, current_shape, preview, config) {
+            // reversed because we use "pop" to fetch the next move
+            return enumerate_placements(&board, current_shape)[index]
+                .moves
+                .iter()
+                .rev()
+                .cloned()
+                .collect();
         }
         return Vec::new();
     }
+}
 
-    // Call this function each time a new shape is presented
-    pub fn update_policy(&mut self, grid: &Grid, current_shape: &ShapePosition) {
-        self.moves = Self::decide_moves(grid, current_shape);
+impl Agent for TetrisBot {
+    fn update_policy(&mut self, observation: &Observation) {
+        self.moves = Self::decide_moves(
+            observation.grid,
+            observation.current_shape,
+            observation.queue,
+            &self.config,
+        );
         // println!(
         //     "Chosen moves for {} block: {:?}",
         //     index_to_color_name(current_shape.color_index),
@@ -203,7 +281,11 @@ impl TetrisBot {
         // );
     }
 
-    pub fn pop_next_move(&mut self) -> Option<Move> {
+    fn pop_next_move(&mut self) -> Option<Move> {
         return self.moves.pop();
     }
+
+    fn name(&self) -> String {
+        return "heuristic".to_string();
+    }
 }
@@ -0,0 +1,387 @@
+// Headless benchmark of an agent: plays seeded games in parallel, and summarizes
+// the results so that bot changes can be compared between commits.
+// Two agents can also play versus matches against each other.
+use std::{
+    fs::File,
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    agents::{agent_from_name, Agent, AgentConfig},
+    ai::SearchConfig,
+    engine::Engine,
+    moves::Move,
+    stats::{Histogram, Summary},
+    versus::{AttackTable, VersusMode, VersusState},
+};
+
+#[derive(Serialize, Clone, Debug)]
+pub struct GameResult {
+    pub seed: u64,
+    pub points: i32,
+    pub lines_cleared: i32,
+    pub level: i32,
+    pub pieces: usize,
+    // false if the game was stopped by the piece cap
+    pub topped_out: bool,
+    pub seconds: f32,
+}
+
+impl GameResult {
+    pub fn pieces_per_second(&self) -> f32 {
+        return self.pieces as f32 / self.seconds.max(f32::EPSILON);
+    }
+
+    pub fn lines_per_piece(&self) -> f32 {
+        return self.lines_cleared as f32 / self.pieces.max(1) as f32;
+    }
+}
+
+// Plays a whole game with `agent`, without rendering or timing, until it tops out
+// or `max_pieces` pieces are locked
+pub fn play_game(agent: &mut dyn Agent, seed: u64, max_pieces: Option<usize>) -> GameResult {
+    let start_time = Instant::now();
+    let mut engine = Engine::new(seed);
+    let mut pieces = 0;
+    agent.update_policy(&engine.observation());
+
+    while !engine.is_game_over() && max_pieces.is_none_or(|max_pieces| pieces < max_pieces) {
+        // an agent running out of moves gets its piece dropped where it is
+        let move_ = agent.pop_next_move().unwrap_or(Move::HardDrop);
+        if engine.apply_move(&move_) {
+            pieces += 1;
+            if !engine.is_game_over() {
+                agent.update_policy(&engine.observation());
+            }
+        }
+    }
+
+    return GameResult {
+        seed,
+        points: engine.score.points,
+        lines_cleared: engine.score.total_lines_cleared,
+        level: engine.score.level,
+        pieces,
+        topped_out: engine.is_game_over(),
+        seconds: start_time.elapsed().as_secs_f32(),
+    };
+}
+
+// plays moves until the current piece gets locked
+fn play_piece(agent: &mut dyn Agent, engine: &mut Engine) {
+    loop {
+        // an agent running out of moves gets its piece dropped where it is
+        let move_ = agent.pop_next_move().unwrap_or(Move::HardDrop);
+        if engine.apply_move(&move_) {
+            return;
+        }
+    }
+}
+
+// Outcome of a versus match, where everything is indexed by agent
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct MatchResult {
+    pub seed: u64,
+    // the agent that placed its pieces first in each turn
+    pub first_player: usize,
+    // None on a draw, when both agents reached the piece cap
+    pub winner: Option<usize>,
+    pub pieces: [usize; 2],
+    pub lines_cleared: [i32; 2],
+    pub lines_sent: [u32; 2],
+}
+
+impl MatchResult {
+    // 1 for a win, 0.5 for a draw and 0 for a loss
+    pub fn score_of(&self, agent: usize) -> f32 {
+        return match self.winner {
+            Some(winner) if winner == agent => 1.,
+            Some(_) => 0.,
+            None => 0.5,
+        };
+    }
+}
+
+// Plays a versus match between two agents, without rendering or timing. They take turns
+// placing one piece each, with the same piece sequence, until one of them tops out
+// or both placed `max_pieces` pieces.
+pub fn play_versus_match(
+    agents: &mut [Box<dyn Agent>; 2],
+    first_player: usize,
+    seed: u64,
+    attack_table: &AttackTable,
+    max_pieces: Option<usize>,
+) -> MatchResult {
+    let mut engines = [0, 1].map(|_| Engine::with_mode(seed, Box::new(VersusMode)));
+    let mut states = [0, 1].map(|_| VersusState::new(attack_table.clone()));
+    let mut pieces = [0; 2];
+    let mut lines_sent = [0; 2];
+    for (agent, engine) in agents.iter_mut().zip(&engines) {
+        agent.update_policy(&engine.observation());
+    }
+
+    let winner = 'turns: loop {
+        if max_pieces.is_some_and(|max_pieces| pieces.iter().all(|&n| n >= max_pieces)) {
+            break None;
+        }
+        for player in [first_player, 1 - first_player] {
+            let engine = &mut engines[player];
+            play_piece(agents[player].as_mut(), engine);
+            pieces[player] += 1;
+            if let Some(lock) = engine.last_lock() {
+                states[player].on_piece_locked(&lock, engine);
+            }
+            let sent = states[player].take_outgoing();
+            lines_sent[player] += sent;
+            states[1 - player].receive(sent);
+            if engine.is_game_over() {
+                break 'turns Some(1 - player);
+            }
+            agents[player].update_policy(&engine.observation());
+        }
+    };
+
+    return MatchResult {
+        seed,
+        first_player,
+        winner,
+        pieces,
+        lines_cleared: [0, 1].map(|i| engines[i].score.total_lines_cleared),
+        lines_sent,
+    };
+}
+
+// Plays two matches per seed in parallel, one with each agent placing its pieces first
+// (so that neither gets the advantage of moving first), each with fresh agents
+pub fn run_versus_matches(
+    agents: [(&str, &AgentConfig); 2],
+    seeds: &[u64],
+    attack_table: &AttackTable,
+    max_pieces: Option<usize>,
+) -> Result<Vec<MatchResult>, String> {
+    let matches: Vec<(u64, usize)> = seeds
+        .iter()
+        .flat_map(|&seed| [(seed, 0), (seed, 1)])
+        .collect();
+    return matches
+        .par_iter()
+        .map(|&(seed, first_player)| {
+            let mut match_agents = [
+                agent_from_name(agents[0].0, agents[0].1)?,
+                agent_from_name(agents[1].0, agents[1].1)?,
+            ];
+            return Ok(play_versus_match(
+                &mut match_agents,
+                first_player,
+                seed,
+                attack_table,
+                max_pieces,
+            ));
+        })
+        .collect();
+}
+
+// Plays one game per seed in parallel, each with a fresh agent.
+// Results are in the order of `seeds`.
+pub fn run_games(
+    agent_name: &str,
+    agent_config: &AgentConfig,
+    seeds: &[u64],
+    max_pieces: Option<usize>,
+) -> Result<Vec<GameResult>, String> {
+    return seeds
+        .par_iter()
+        .map(|&seed| {
+            let mut agent = agent_from_name(agent_name, agent_config)?;
+            return Ok(play_game(agent.as_mut(), seed, max_pieces));
+        })
+        .collect();
+}
+
+// Agent configuration given as "name[:key=value,...]", eg "heuristic:lookahead=2,beam_width=8".
+// Keys: lookahead, beam_width, expect_unknown, think_ms and bot_command.
+pub fn parse_agent_spec(spec: &str) -> Result<(String, AgentConfig), String> {
+    let (name, settings) = spec.split_once(':').unwrap_or((spec, ""));
+    let mut search_config = SearchConfig::default();
+    let mut bot_command = None;
+    for setting in settings.split(',').filter(|setting| !setting.is_empty()) {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or(format!("expected key=value, got '{}'", setting))?;
+        let invalid_value = || format!("invalid value '{}' for {}", value, key);
+        match key {
+            "lookahead" => search_config.lookahead = value.parse().map_err(|_| invalid_value())?,
+            "beam_width" => search_config.beam_width = value.parse().map_err(|_| invalid_value())?,
+            "expect_unknown" => {
+                search_config.expect_unknown_piece = value.parse().map_err(|_| invalid_value())?
+            }
+            "think_ms" => {
+                search_config.time_budget = Some(Duration::from_millis(
+                    value.parse().map_err(|_| invalid_value())?,
+                ))
+            }
+            "bot_command" => bot_command = Some(value.to_string()),
+            _ => return Err(format!("unknown agent setting '{}'", key)),
+        }
+    }
+    return Ok((
+        name.to_string(),
+        AgentConfig {
+            search_config,
+            bot_command,
+        },
+    ));
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct BenchmarkReport {
+    // free-form description of the configuration (agent, search settings...)
+    pub label: String,
+    pub n_games: usize,
+    pub max_pieces: Option<usize>,
+    pub wall_seconds: f32,
+    pub top_out_rate: f32,
+    pub points: Summary,
+    pub lines_cleared: Summary,
+    pub level: Summary,
+    pub pieces: Summary,
+    pub pieces_per_second: Summary,
+    pub lines_per_piece: Summary,
+    pub points_histogram: Histogram,
+}
+
+impl BenchmarkReport {
+    const N_HISTOGRAM_BINS: usize = 10;
+
+    pub fn new(
+        label: &str,
+        results: &[GameResult],
+        max_pieces: Option<usize>,
+        wall_seconds: f32,
+    ) -> Self {
+        let metric = |f: fn(&GameResult) -> f32| {
+            return Summary::new(&results.iter().map(f).collect::<Vec<f32>>());
+        };
+        let points: Vec<f32> = results.iter().map(|result| result.points as f32).collect();
+        let n_topped_out = results.iter().filter(|result| result.topped_out).count();
+        return BenchmarkReport {
+            label: label.to_string(),
+            n_games: results.len(),
+            max_pieces,
+            wall_seconds,
+            top_out_rate: n_topped_out as f32 / results.len().max(1) as f32,
+            points: metric(|result| result.points as f32),
+            lines_cleared: metric(|result| result.lines_cleared as f32),
+            level: metric(|result| result.level as f32),
+            pieces: metric(|result| result.pieces as f32),
+            pieces_per_second: metric(GameResult::pieces_per_second),
+            lines_per_piece: metric(GameResult::lines_per_piece),
+            points_histogram: Histogram::new(&points, Self::N_HISTOGRAM_BINS),
+        };
+    }
+
+    pub fn print(&self) {
+        println!("{} ({} games)", self.label, self.n_games);
+        println!(
+            "{:<18} {:>10} {:>10} {:>25} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "", "mean", "sd", "95% CI", "min", "p10", "median", "p90", "max"
+        );
+        let metrics = [
+            ("points", &self.points),
+            ("lines cleared", &self.lines_cleared),
+            ("level", &self.level),
+            ("pieces", &self.pieces),
+            ("pieces/s", &self.pieces_per_second),
+            ("lines/piece", &self.lines_per_piece),
+        ];
+        for (name, metric) in metrics {
+            println!(
+                "{:<18} {:>10.3} {:>10.3} {:>25} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+                name,
+                metric.mean,
+                metric.sd,
+                format!("[{:.3}, {:.3}]", metric.ci_low, metric.ci_high),
+                metric.min,
+                metric.p10,
+                metric.median,
+                metric.p90,
+                metric.max
+            );
+        }
+        println!(
+            "topped out in {:.1}% of the games, {:.1}s in total",
+            self.top_out_rate * 100.,
+            self.wall_seconds
+        );
+        println!("points:");
+        println!("{}", self.points_histogram.render(50));
+    }
+
+    pub fn write_json(&self, path: &str) -> io::Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        return Ok(());
+    }
+}
+
+// one line per game
+pub fn write_csv(path: &str, results: &[GameResult]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(
+        file,
+        "seed,points,lines_cleared,level,pieces,topped_out,seconds,pieces_per_second,lines_per_piece"
+    )?;
+    for result in results {
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{}",
+            result.seed,
+            result.points,
+            result.lines_cleared,
+            result.level,
+            result.pieces,
+            result.topped_out,
+            result.seconds,
+            result.pieces_per_second(),
+            result.lines_per_piece()
+        )?;
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heuristic_agents() -> [Box<dyn Agent>; 2] {
+        let config = AgentConfig {
+            search_config: SearchConfig::default(),
+            bot_command: None,
+        };
+        return [0, 1].map(|_| agent_from_name("heuristic", &config).unwrap());
+    }
+
+    #[test]
+    fn versus_matches_are_reproducible() {
+        let attack_table = AttackTable::default();
+        for first_player in [0, 1] {
+            let results: Vec<MatchResult> = (0..2)
+                .map(|_| {
+                    return play_versus_match(
+                        &mut heuristic_agents(),
+                        first_player,
+                        7,
+                        &attack_table,
+                        Some(40),
+                    );
+                })
+                .collect();
+            assert_eq!(results[0], results[1]);
+            assert_eq!(results[0].first_player, first_player);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+use rustop::opts;
+
+use tetrus::{
+    agents::AgentConfig,
+    ai::SearchConfig,
+    benchmark::{run_games, write_csv, BenchmarkReport},
+};
+
+// Plays seeded games headlessly and in parallel, eg:
+// cargo run --release --bin benchmark -- --n-games 200 --json bench.json
+fn main() {
+    let (args, _rest) = opts! {
+        synopsis "Benchmark of the tetrus AIs on seeded games.";
+        opt agent: String=String::from("heuristic"), desc:"AI to benchmark: heuristic, random or tbp";
+        opt bot_command: Option<String>, desc:"Command launching an external bot, for the tbp agent";
+        opt n_games: usize=100, desc:"Number of games to play";
+        opt first_seed: u64=0, desc:"Seed of the first game (the others follow)";
+        opt max_pieces: usize=500, desc:"Stop each game after that many pieces (0 for no cap)";
+        opt lookahead: usize=1, desc:"Number of preview pieces the AI looks ahead";
+        opt beam_width: usize=5, desc:"Number of candidate boards the AI keeps at each step";
+        opt expect_unknown: bool, desc:"Make the AI average over the unknown piece after the preview";
+        opt think_ms: Option<u64>, desc:"Time budget (ms) for each AI decision (default: none, for reproducible results)";
+        opt threads: Option<usize>, desc:"Number of threads (default: all cores)";
+        opt csv: Option<String>, desc:"Write the result of each game to this CSV file";
+        opt json: Option<String>, desc:"Write the summary to this JSON file";
+    }
+    .parse_or_exit();
+
+    if let Some(n_threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(n_threads)
+            .build_global()
+            .unwrap();
+    }
+
+    let search_config = SearchConfig {
+        lookahead: args.lookahead,
+        beam_width: args.beam_width,
+        expect_unknown_piece: args.expect_unknown,
+        time_budget: args.think_ms.map(Duration::from_millis),
+    };
+    let label = match args.agent.as_str() {
+        "heuristic" => format!("heuristic {:?}", search_config),
+        "tbp" => format!("tbp {:?}", args.bot_command),
+        name => name.to_string(),
+    };
+    let agent_config = AgentConfig {
+        search_config,
+        bot_command: args.bot_command,
+    };
+    let max_pieces = (args.max_pieces > 0).then_some(args.max_pieces);
+    let seeds: Vec<u64> = (args.first_seed..args.first_seed + args.n_games as u64).collect();
+    if seeds.is_empty() {
+        eprintln!("Error: no game to play");
+        std::process::exit(1);
+    }
+
+    let start_time = Instant::now();
+    let results = run_games(&args.agent, &agent_config, &seeds, max_pieces).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    });
+    let report = BenchmarkReport::new(
+        &label,
+        &results,
+        max_pieces,
+        start_time.elapsed().as_secs_f32(),
+    );
+    report.print();
+
+    if let Some(path) = args.csv {
+        write_csv(&path, &results).expect("could not write the CSV file");
+    }
+    if let Some(path) = args.json {
+        report.write_json(&path).expect("could not write the JSON file");
+    }
+}
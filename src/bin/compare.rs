@@ -0,0 +1,88 @@
+use rustop::opts;
+
+use tetrus::{
+    benchmark::{parse_agent_spec, run_games, GameResult},
+    stats,
+};
+
+// name of a metric, and how to get it from the result of a game
+type Metric = (&'static str, fn(&GameResult) -> f32);
+
+// Plays two agent configurations on the same seeds, and tests whether B differs from A, eg:
+// cargo run --release --bin compare -- --baseline heuristic --candidate heuristic:lookahead=2
+fn main() {
+    let (args, _rest) = opts! {
+        synopsis "Paired comparison of two tetrus AI configurations on identical seeds.";
+        opt baseline: String, desc:"Baseline configuration, as agent[:key=value,...] (keys: lookahead, beam_width, expect_unknown, think_ms, bot_command)";
+        opt candidate: String, desc:"Candidate configuration, in the same format";
+        opt n_games: usize=100, desc:"Number of seeds (each one is played by both configurations)";
+        opt first_seed: u64=0, desc:"Seed of the first game (the others follow)";
+        opt max_pieces: usize=500, desc:"Stop each game after that many pieces (0 for no cap)";
+        opt significance: f32=0.05, desc:"Significance level of the test";
+        opt threads: Option<usize>, desc:"Number of threads (default: all cores)";
+    }
+    .parse_or_exit();
+
+    if let Some(n_threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(n_threads)
+            .build_global()
+            .unwrap();
+    }
+    if args.n_games < 2 {
+        eprintln!("Error: the comparison needs at least 2 games");
+        std::process::exit(1);
+    }
+
+    let max_pieces = (args.max_pieces > 0).then_some(args.max_pieces);
+    let seeds: Vec<u64> = (args.first_seed..args.first_seed + args.n_games as u64).collect();
+    let mut all_results = Vec::new();
+    for spec in [&args.baseline, &args.candidate] {
+        let results = parse_agent_spec(spec)
+            .and_then(|(name, config)| run_games(&name, &config, &seeds, max_pieces))
+            .unwrap_or_else(|err| {
+                eprintln!("Error with configuration '{}': {}", spec, err);
+                std::process::exit(1);
+            });
+        all_results.push(results);
+    }
+
+    println!("A: {}", args.baseline);
+    println!("B: {}", args.candidate);
+    println!("{} paired games (B - A):", seeds.len());
+    println!(
+        "{:<14} {:>10} {:>10} {:>10} {:>23} {:>10} {:>16} {:>8} {:>8}",
+        "", "mean A", "mean B", "mean diff", "95% CI", "med diff", "B >/=/< A", "t", "p"
+    );
+    let metrics: [Metric; 4] = [
+        ("points", |result| result.points as f32),
+        ("lines cleared", |result| result.lines_cleared as f32),
+        ("pieces", |result| result.pieces as f32),
+        ("lines/piece", GameResult::lines_per_piece),
+    ];
+    for (name, metric) in metrics {
+        let a: Vec<f32> = all_results[0].iter().map(metric).collect();
+        let b: Vec<f32> = all_results[1].iter().map(metric).collect();
+        let test = stats::paired_t_test(&a, &b);
+        let verdict = if test.p_value >= args.significance {
+            ""
+        } else if test.mean_diff > 0. {
+            "B better"
+        } else {
+            "B worse"
+        };
+        println!(
+            "{:<14} {:>10.3} {:>10.3} {:>10.3} {:>23} {:>10.3} {:>16} {:>8.3} {:>8.4} {}",
+            name,
+            stats::float_avg(&a),
+            stats::float_avg(&b),
+            test.mean_diff,
+            format!("[{:.3}, {:.3}]", test.ci_low, test.ci_high),
+            test.median_diff,
+            format!("{}/{}/{}", test.n_higher, test.n_ties, test.n_lower),
+            test.t,
+            test.p_value,
+            verdict
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::{fs::File, time::Instant};
+
+use rustop::opts;
+
+use tetrus::{
+    benchmark::{parse_agent_spec, run_versus_matches},
+    stats,
+    versus::AttackTable,
+};
+
+// Pits two agent configurations against each other in headless versus matches, eg:
+// cargo run --release --bin versus -- --baseline heuristic --candidate heuristic:lookahead=2
+fn main() {
+    let (args, _rest) = opts! {
+        synopsis "Versus matches between two tetrus AI configurations, with garbage exchange.";
+        opt baseline: String, desc:"Baseline configuration, as agent[:key=value,...] (keys: lookahead, beam_width, expect_unknown, think_ms, bot_command)";
+        opt candidate: String, desc:"Candidate configuration, in the same format";
+        opt n_seeds: usize=50, desc:"Number of seeds (each one is played twice, with either agent moving first)";
+        opt first_seed: u64=0, desc:"Seed of the first match (the others follow)";
+        opt max_pieces: usize=500, desc:"Call a draw once both agents placed that many pieces (0 for no cap)";
+        opt attack_table: Option<String>, desc:"JSON file with the lines of garbage sent for each line clear";
+        opt threads: Option<usize>, desc:"Number of threads (default: all cores)";
+        opt json: Option<String>, desc:"Write the result of each match to this JSON file";
+    }
+    .parse_or_exit();
+
+    if let Some(n_threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(n_threads)
+            .build_global()
+            .unwrap();
+    }
+    let attack_table =
+        AttackTable::load_or_default(args.attack_table.as_deref()).unwrap_or_else(|err| {
+            eprintln!("Error: could not read the attack table {}", err);
+            std::process::exit(1);
+        });
+    let specs = [&args.baseline, &args.candidate].map(|spec| {
+        return parse_agent_spec(spec).unwrap_or_else(|err| {
+            eprintln!("Error with configuration '{}': {}", spec, err);
+            std::process::exit(1);
+        });
+    });
+
+    let max_pieces = (args.max_pieces > 0).then_some(args.max_pieces);
+    let seeds: Vec<u64> = (args.first_seed..args.first_seed + args.n_seeds as u64).collect();
+    let start_time = Instant::now();
+    let results = run_versus_matches(
+        [(&specs[0].0, &specs[0].1), (&specs[1].0, &specs[1].1)],
+        &seeds,
+        &attack_table,
+        max_pieces,
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    });
+
+    println!("A: {}", args.baseline);
+    println!("B: {}", args.candidate);
+    let count = |winner: Option<usize>| {
+        return results
+            .iter()
+            .filter(|result| result.winner == winner)
+            .count();
+    };
+    println!(
+        "{} matches ({} seeds, each agent moving first once), {:.1}s",
+        results.len(),
+        seeds.len(),
+        start_time.elapsed().as_secs_f32()
+    );
+    println!(
+        "B wins {}, A wins {}, draws {}",
+        count(Some(1)),
+        count(Some(0)),
+        count(None)
+    );
+    let scores: Vec<f32> = results.iter().map(|result| result.score_of(1)).collect();
+    let (ci_low, ci_high) = stats::mean_confidence_interval(&scores);
+    println!(
+        "B win rate: {:.1}% [{:.1}%, {:.1}%] (draws count as half a win)",
+        stats::float_avg(&scores) * 100.,
+        ci_low * 100.,
+        ci_high * 100.
+    );
+    for (agent, name) in ["A", "B"].iter().enumerate() {
+        let lines_sent_per_piece: Vec<f32> = results
+            .iter()
+            .map(|result| result.lines_sent[agent] as f32 / result.pieces[agent].max(1) as f32)
+            .collect();
+        let pieces: Vec<f32> = results
+            .iter()
+            .map(|result| result.pieces[agent] as f32)
+            .collect();
+        println!(
+            "{}: {:.3} lines sent per piece, {:.1} pieces per match",
+            name,
+            stats::float_avg(&lines_sent_per_piece),
+            stats::float_avg(&pieces)
+        );
+    }
+
+    if let Some(path) = args.json {
+        let file = File::create(&path).expect("could not create the JSON file");
+        serde_json::to_writer_pretty(file, &results).expect("could not write the JSON file");
+    }
+}
@@ -0,0 +1,124 @@
+use crate::constants::{GRID_HEIGHT, GRID_WIDTH};
+
+const N_ROWS: usize = GRID_HEIGHT as usize;
+const N_COLS: usize = GRID_WIDTH as usize;
+pub const FULL_ROW: u16 = (1 << N_COLS) - 1;
+
+// Compact version of the grid, used by the bot search and the feature extraction.
+// Bit j of row i is set when cell (i, j) is occupied. Colors are not tracked here.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BitBoard {
+    rows: [u16; N_ROWS],
+    // number of cells between the bottom of the grid and the top of each column
+    // (0 for an empty column), kept in sync with `rows`
+    heights: [u8; N_COLS],
+}
+
+impl BitBoard {
+    pub fn new() -> Self {
+        BitBoard {
+            rows: [0; N_ROWS],
+            heights: [0; N_COLS],
+        }
+    }
+
+    pub fn rows(&self) -> &[u16; N_ROWS] {
+        return &self.rows;
+    }
+
+    pub fn column_heights(&self) -> &[u8; N_COLS] {
+        return &self.heights;
+    }
+
+    pub fn is_filled(&self, i: i16, j: i16) -> bool {
+        return self.rows[i as usize] & (1 << j) != 0;
+    }
+
+    pub fn can_set_pixels(&self, pixels: &[(i16, i16)]) -> bool {
+        for &(i, j) in pixels {
+            if i < 0 || j < 0 || i >= GRID_HEIGHT || j >= GRID_WIDTH {
+                return false;
+            }
+            if self.is_filled(i, j) {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    pub fn set_pixels(&mut self, pixels: &[(i16, i16)]) {
+        for &(i, j) in pixels {
+            self.rows[i as usize] |= 1 << j;
+            let height = (GRID_HEIGHT - i) as u8;
+            if height > self.heights[j as usize] {
+                self.heights[j as usize] = height;
+            }
+        }
+    }
+
+    pub fn unset_pixels(&mut self, pixels: &[(i16, i16)]) {
+        for &(i, j) in pixels {
+            self.rows[i as usize] &= !(1 << j);
+        }
+        self.update_heights();
+    }
+
+    fn update_heights(&mut self) {
+        self.heights = [0; N_COLS];
+        let mut seen: u16 = 0;
+        for (i, row) in self.rows.iter().enumerate() {
+            let new_cols = row & !seen;
+            if new_cols != 0 {
+                for j in 0..N_COLS {
+                    if new_cols & (1 << j) != 0 {
+                        self.heights[j] = (N_ROWS - i) as u8;
+                    }
+                }
+                seen |= new_cols;
+            }
+        }
+    }
+
+    // pushes the stack up, adding `new_rows` (from top to bottom) at the bottom of the grid.
+    // returns a bool indicating whether some filled cells got pushed out of the top
+    pub fn push_rows_up(&mut self, new_rows: &[u16]) -> bool {
+        let n = new_rows.len().min(N_ROWS);
+        let is_topped_out = self.rows[..n].iter().any(|&row| row != 0);
+        self.rows.copy_within(n.., 0);
+        self.rows[N_ROWS - n..].copy_from_slice(&new_rows[new_rows.len() - n..]);
+        self.update_heights();
+        return is_topped_out;
+    }
+
+    // indices of the full rows, from top to bottom
+    pub fn completed_rows(&self) -> Vec<usize> {
+        return (0..N_ROWS).filter(|&i| self.rows[i] == FULL_ROW).collect();
+    }
+
+    // same gravity as `Grid::clear_completed_rows`
+    pub fn clear_completed_rows(&mut self) -> i32 {
+        let mut n_cleared = 0;
+        let mut write = N_ROWS;
+        for read in (0..N_ROWS).rev() {
+            if self.rows[read] == FULL_ROW {
+                n_cleared += 1;
+                continue;
+            }
+            write -= 1;
+            self.rows[write] = self.rows[read];
+        }
+        for i in 0..write {
+            self.rows[i] = 0;
+        }
+        if n_cleared > 0 {
+            self.update_heights();
+        }
+        return n_cleared;
+    }
+}
+
+impl Default for BitBoard {
+    fn default() -> Self {
+        return BitBoard::new();
+    }
+}
@@ -2,6 +2,9 @@
 pub const GRID_HEIGHT: i16 = 20;
 pub const GRID_WIDTH: i16 = 10;
 
+// number of upcoming pieces shown next to the grid
+pub const PREVIEW_SIZE: usize = 3;
+
 // pixel drawing constants
 pub const BLOCK_SIZE: i16 = 30;
 pub const CELL_BORDER: f32 = 2.;
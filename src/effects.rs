@@ -0,0 +1,136 @@
+// Visual feedback of the line clears. The engine removes the rows right away: the game only
+// holds the next piece back while the animation plays, drawing the grid as it was before.
+use std::time::{Duration, Instant};
+
+use macroquad::{
+    prelude::{vec2, Color, Vec2},
+    shapes::draw_rectangle,
+};
+use rand::Rng;
+
+use crate::{
+    constants::{BLOCK_SIZE, GRID_WIDTH},
+    engine::LineClear,
+};
+
+pub struct LineClearAnimation {
+    line_clear: LineClear,
+    start_time: Instant,
+    duration: Duration,
+}
+
+impl LineClearAnimation {
+    pub const DURATION: Duration = Duration::from_millis(300);
+
+    // (faster when the game is sped up)
+    pub fn new(line_clear: LineClear, speedup_rate: u32) -> Self {
+        return LineClearAnimation {
+            line_clear,
+            start_time: Instant::now(),
+            duration: Self::DURATION / speedup_rate,
+        };
+    }
+
+    // from 0 to 1
+    fn progress(&self) -> f32 {
+        let progress = self.start_time.elapsed().as_secs_f32() / self.duration.as_secs_f32();
+        return progress.min(1.);
+    }
+
+    pub fn draw(&self) {
+        self.line_clear
+            .grid_before
+            .draw_clearing(&self.line_clear.rows, self.progress());
+    }
+}
+
+struct Particle {
+    // in pixels, from the top-left corner of the grid
+    position: Vec2,
+    // in pixels per second
+    velocity: Vec2,
+    color: Color,
+    // seconds since it got emitted
+    age: f32,
+}
+
+// Sparks that burst out of the rows of a tetris and fall off
+pub struct Particles {
+    particles: Vec<Particle>,
+}
+
+impl Particles {
+    const PER_CELL: usize = 3;
+    // in seconds
+    const LIFETIME: f32 = 1.2;
+    // in pixels per second squared
+    const GRAVITY: f32 = 900.;
+    const MAX_SPEED: f32 = 400.;
+    const SIZE: f32 = 5.;
+
+    pub fn new() -> Self {
+        return Particles {
+            particles: Vec::new(),
+        };
+    }
+
+    pub fn burst(&mut self, line_clear: &LineClear) {
+        let mut rng = rand::thread_rng();
+        let block_size = BLOCK_SIZE as f32;
+        for &row in &line_clear.rows {
+            for column in 0..GRID_WIDTH {
+                let Some(color) = line_clear.grid_before.cell_color(row as i16, column) else {
+                    continue;
+                };
+                let center = vec2(
+                    (column as f32 + 0.5) * block_size,
+                    (row as f32 + 0.5) * block_size,
+                );
+                for _ in 0..Self::PER_CELL {
+                    // mostly upwards
+                    let velocity = vec2(
+                        rng.gen_range(-1. ..1.) * Self::MAX_SPEED,
+                        rng.gen_range(-1. ..0.3) * Self::MAX_SPEED,
+                    );
+                    self.particles.push(Particle {
+                        position: center,
+                        velocity,
+                        color,
+                        age: 0.,
+                    });
+                }
+            }
+        }
+    }
+
+    // moves the particles by `dt` seconds, dropping the ones that faded out
+    pub fn update(&mut self, dt: f32) {
+        for particle in self.particles.iter_mut() {
+            particle.velocity.y += Self::GRAVITY * dt;
+            particle.position += particle.velocity * dt;
+            particle.age += dt;
+        }
+        self.particles
+            .retain(|particle| particle.age < Self::LIFETIME);
+    }
+
+    pub fn draw(&self) {
+        for particle in &self.particles {
+            let mut color = particle.color;
+            color.a = 1. - particle.age / Self::LIFETIME;
+            draw_rectangle(
+                particle.position.x - Self::SIZE / 2.,
+                particle.position.y - Self::SIZE / 2.,
+                Self::SIZE,
+                Self::SIZE,
+                color,
+            );
+        }
+    }
+}
+
+impl Default for Particles {
+    fn default() -> Self {
+        return Particles::new();
+    }
+}
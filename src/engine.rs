@@ -0,0 +1,422 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    agents::Observation,
+    constants::GRID_WIDTH,
+    game_stats::{is_t_spin, GameStats},
+    gravity::{GravityCurve, MAX_LOCK_RESETS},
+    grid::Grid,
+    modes::{GameMode, Marathon},
+    moves::{Move, SimpleMove},
+    score::Score,
+    shapes::{ShapePosition, ShapeQueue},
+};
+
+// 64-bit FNV-1a hash
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    return hash;
+}
+
+// what happened when the last piece got locked
+#[derive(Clone, Copy, Debug)]
+pub struct PieceLocked {
+    pub lines_cleared: i32,
+    pub is_t_spin: bool,
+}
+
+// rows cleared by the last piece locked, and the grid right before they got removed
+// (to animate the line clear, while the game already moved on)
+#[derive(Clone)]
+pub struct LineClear {
+    pub rows: Vec<usize>,
+    pub grid_before: Grid,
+}
+
+// The rules of the game, without any rendering, sound or timing.
+// Given the same seed and the same inputs, it always plays out the same way.
+pub struct Engine {
+    grid: Grid,
+    // info about the tetromino that the user currently controls
+    current_shape: ShapePosition,
+    next_shapes: ShapeQueue,
+
+    mode: Box<dyn GameMode>,
+    gravity_curve: GravityCurve,
+    // rows the current piece has to fall by, that don't add up to a whole row yet
+    rows_due: f64,
+    // frames the current piece has been resting on the stack, since it landed or last moved
+    resting_frames: f64,
+    lock_resets: u32,
+    pub score: Score,
+    pub stats: GameStats,
+    is_game_over: bool,
+    // whether the current piece last moved by rotating (to detect T-spins)
+    last_move_was_rotation: bool,
+    last_lock: Option<PieceLocked>,
+    last_line_clear: Option<LineClear>,
+    // picks the holes of the random garbage rows
+    garbage_rng: StdRng,
+}
+
+impl Engine {
+    // a marathon game
+    pub fn new(seed: u64) -> Self {
+        return Self::with_mode(seed, Box::new(Marathon));
+    }
+
+    pub fn with_mode(seed: u64, mode: Box<dyn GameMode>) -> Self {
+        let mut next_shapes = ShapeQueue::new(
+            mode.preview_size(),
+            seed,
+            mode.randomizer(),
+            mode.rotation_system(),
+        );
+        let current_shape = next_shapes.pop();
+
+        let mut engine = Engine {
+            grid: Grid::new(),
+            current_shape,
+            next_shapes,
+            mode,
+            gravity_curve: GravityCurve::Guideline,
+            rows_due: 0.,
+            resting_frames: 0.,
+            lock_resets: 0,
+            score: Score::new(0),
+            stats: GameStats::new(),
+            is_game_over: false,
+            last_move_was_rotation: false,
+            last_lock: None,
+            last_line_clear: None,
+            // (not the same sequence as the pieces)
+            garbage_rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+        };
+        let n_garbage_rows = engine.mode.initial_garbage_rows();
+        let holes = engine.random_garbage_holes(n_garbage_rows);
+        engine.grid.add_garbage_rows(&holes);
+        engine.add_shape_to_grid();
+        return engine;
+    }
+
+    pub fn grid(&self) -> &Grid {
+        return &self.grid;
+    }
+
+    pub fn current_shape(&self) -> &ShapePosition {
+        return &self.current_shape;
+    }
+
+    pub fn preview(&self) -> &[ShapePosition] {
+        return self.next_shapes.preview();
+    }
+
+    pub fn next_shapes(&self) -> &ShapeQueue {
+        return &self.next_shapes;
+    }
+
+    pub fn mode(&self) -> &dyn GameMode {
+        return self.mode.as_ref();
+    }
+
+    // the one picked by the player, unless the mode has its own
+    pub fn gravity_curve(&self) -> GravityCurve {
+        return self.mode.gravity_curve(self.gravity_curve);
+    }
+
+    pub fn set_gravity_curve(&mut self, gravity_curve: GravityCurve) {
+        self.gravity_curve = gravity_curve;
+    }
+
+    // current gravity, in rows per frame
+    pub fn gravity(&self) -> f64 {
+        return self.mode.gravity(self.gravity_curve(), &self.score);
+    }
+
+    // only before the game starts
+    pub fn set_start_level(&mut self, start_level: i32) {
+        self.score = Score::new(start_level);
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        return self.is_game_over;
+    }
+
+    pub fn last_lock(&self) -> Option<PieceLocked> {
+        return self.last_lock;
+    }
+
+    // fingerprint of the state of the game, to check that two copies of a game played out the same.
+    // It must not depend on the platform or on the version of Rust, unlike the standard hasher
+    pub fn state_hash(&self) -> u64 {
+        let mut bytes: Vec<u8> = Vec::new();
+        for row in self.grid.bitboard().rows() {
+            bytes.extend(row.to_le_bytes());
+        }
+        for shape in std::iter::once(&self.current_shape).chain(self.next_shapes.preview()) {
+            bytes.push(shape.shape_index() as u8);
+            bytes.push(shape.color_index as u8);
+            for (i, j) in shape.get_pixels() {
+                bytes.extend(i.to_le_bytes());
+                bytes.extend(j.to_le_bytes());
+            }
+        }
+        bytes.extend(self.score.points.to_le_bytes());
+        bytes.extend(self.score.total_lines_cleared.to_le_bytes());
+        bytes.push(self.is_game_over as u8);
+        return fnv1a(&bytes);
+    }
+
+    pub fn last_line_clear(&self) -> Option<&LineClear> {
+        return self.last_line_clear.as_ref();
+    }
+
+    pub fn observation(&self) -> Observation<'_> {
+        return Observation {
+            grid: &self.grid,
+            current_shape: &self.current_shape,
+            queue: self.next_shapes.preview(),
+            hold: None,
+            score: &self.score,
+        };
+    }
+
+    pub fn random_garbage_hole(&mut self) -> i16 {
+        return self.garbage_rng.gen_range(0..GRID_WIDTH);
+    }
+
+    // one hole per row, never in the same column in two adjacent rows
+    // (so that each row has to be dug through separately)
+    pub fn random_garbage_holes(&mut self, n_rows: usize) -> Vec<i16> {
+        let mut holes: Vec<i16> = Vec::with_capacity(n_rows);
+        for _ in 0..n_rows {
+            let mut hole = self.random_garbage_hole();
+            if holes.last() == Some(&hole) {
+                hole = (hole + self.garbage_rng.gen_range(1..GRID_WIDTH)) % GRID_WIDTH;
+            }
+            holes.push(hole);
+        }
+        return holes;
+    }
+
+    // pushes the stack up with one garbage row per hole column, the last one at the bottom.
+    // the current piece stays where it is, and the game is over if it no longer fits
+    // or if the stack got pushed out of the grid
+    pub fn add_garbage(&mut self, holes: &[i16]) {
+        if self.is_game_over || holes.is_empty() {
+            return;
+        }
+        self.clear_shape_from_grid();
+        let is_topped_out = self.grid.add_garbage_rows(holes);
+        if is_topped_out || !self.is_valid_add(&self.current_shape) {
+            self.is_game_over = true;
+        }
+        self.add_shape_to_grid();
+    }
+
+    fn get_shape_pixels(&self) -> [(i16, i16); 4] {
+        return self.current_shape.get_pixels();
+    }
+
+    fn is_valid_move(&self, new_pos: &ShapePosition) -> bool {
+        // check if the current shape can be moved from its current position
+        // to its desired next position
+        let new_pixels = new_pos.get_pixels();
+        return self
+            .grid
+            .can_set_pixels(&new_pixels, &self.get_shape_pixels());
+    }
+
+    fn is_valid_add(&self, new_pos: &ShapePosition) -> bool {
+        // check if a new piece can be added to the board (if not, game over)
+        return self.grid.can_set_pixels(&new_pos.get_pixels(), &[]);
+    }
+
+    fn clear_shape_from_grid(&mut self) {
+        self.grid.unset_pixels(&self.get_shape_pixels());
+    }
+
+    fn add_shape_to_grid(&mut self) {
+        self.grid.set_pixels(
+            &self.get_shape_pixels(),
+            self.current_shape.color_index as i32,
+        );
+    }
+
+    fn move_shape_to(&mut self, new_pos: ShapePosition) {
+        self.clear_shape_from_grid();
+        self.current_shape = new_pos;
+        self.add_shape_to_grid();
+    }
+
+    // returns a bool indicating whether the current piece got locked
+    // (in which case the next piece has spawned, unless the game is over)
+    pub fn apply_move(&mut self, move_: &Move) -> bool {
+        if self.is_game_over {
+            return false;
+        }
+        self.stats.on_key_pressed();
+        match move_ {
+            Move::Simple(simple_move) => {
+                let new_pos = self.current_shape.moved_to(simple_move);
+                if self.is_valid_move(&new_pos) {
+                    self.move_shape_to(new_pos);
+                    self.last_move_was_rotation = matches!(
+                        simple_move,
+                        SimpleMove::Rotate | SimpleMove::RotateCounterClockwise
+                    );
+                    self.reset_lock_delay();
+                }
+                return false;
+            }
+            Move::HardDrop => {
+                if !self.mode.has_hard_drop() {
+                    return false;
+                }
+                let mut did_hit_rock_bottom = false;
+                while !did_hit_rock_bottom {
+                    did_hit_rock_bottom = self.perform_block_drop();
+                }
+                return true;
+            }
+        }
+    }
+
+    // Lets the gravity act for that many frames (or a fraction of a frame).
+    // The current piece falls by all the whole rows due, possibly several in a single frame,
+    // and stops on the stack: it only gets locked if a row is due once it rested there
+    // for the lock delay.
+    // returns a bool indicating whether the piece got locked
+    pub fn apply_gravity(&mut self, frames: f64) -> bool {
+        if self.is_game_over {
+            return false;
+        }
+        if !self.is_valid_move(&self.current_shape.moved_to(&SimpleMove::Down)) {
+            self.resting_frames += frames;
+        }
+        self.rows_due += self.gravity() * frames;
+        // (allowing for rounding errors, eg 48 frames at 1/48 rows per frame)
+        let n_rows = (self.rows_due + 1e-9).floor();
+        self.rows_due = (self.rows_due - n_rows).max(0.);
+        for row in 0..n_rows as usize {
+            let new_pos = self.current_shape.moved_to(&SimpleMove::Down);
+            if !self.is_valid_move(&new_pos) {
+                if row == 0 && self.resting_frames >= self.mode.lock_delay() {
+                    return self.perform_block_drop();
+                }
+                break;
+            }
+            self.move_shape_to(new_pos);
+            self.last_move_was_rotation = false;
+            self.resting_frames = 0.;
+        }
+        return false;
+    }
+
+    // moving a piece that rests on the stack gives it the whole lock delay again
+    fn reset_lock_delay(&mut self) {
+        if self.resting_frames > 0. && self.lock_resets < MAX_LOCK_RESETS {
+            self.resting_frames = 0.;
+            self.lock_resets += 1;
+        }
+    }
+
+    // move the current piece one cell down, or lock it if it can't go any further.
+    // returns a bool indicating whether the piece got locked
+    pub fn perform_block_drop(&mut self) -> bool {
+        if self.is_game_over {
+            return false;
+        }
+        // try to move the current piece one cell down
+        let mut new_pos = self.current_shape.moved_to(&SimpleMove::Down);
+        if !self.is_valid_move(&new_pos) {
+            // we can't move down any further: issue a new piece
+            // first, check if any line got cleared
+            let board_locked = self.grid.bitboard();
+            let mut board_before = board_locked;
+            board_before.unset_pixels(&self.get_shape_pixels());
+            let is_t_spin = is_t_spin(
+                &board_locked,
+                &self.current_shape,
+                self.last_move_was_rotation,
+            );
+            let cleared_rows = board_locked.completed_rows();
+            self.last_line_clear = (!cleared_rows.is_empty()).then(|| LineClear {
+                rows: cleared_rows,
+                grid_before: self.grid.clone(),
+            });
+            let n_cleared = self.grid.clear_completed_rows();
+            self.score.update(n_cleared, self.mode.as_ref());
+            self.stats
+                .on_piece_locked(n_cleared, is_t_spin, &board_before, &board_locked);
+            self.last_move_was_rotation = false;
+            self.last_lock = Some(PieceLocked {
+                lines_cleared: n_cleared,
+                is_t_spin,
+            });
+
+            // then drop the next piece, that starts falling from scratch
+            new_pos = self.next_shapes.pop();
+            self.rows_due = 0.;
+            self.resting_frames = 0.;
+            self.lock_resets = 0;
+            if !self.is_valid_add(&new_pos) {
+                // show the piece overlap with existing pieces
+                // for dramatic effect
+                self.current_shape = new_pos;
+                self.add_shape_to_grid();
+                self.is_game_over = true;
+                return true;
+            }
+            self.current_shape = new_pos;
+            self.add_shape_to_grid();
+            return true;
+        } else {
+            self.move_shape_to(new_pos);
+            self.last_move_was_rotation = false;
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gravity::LOCK_DELAY;
+
+    fn is_resting(engine: &Engine) -> bool {
+        return !engine.is_valid_move(&engine.current_shape().moved_to(&SimpleMove::Down));
+    }
+
+    // at 20G, a marathon piece lands on the frame it spawns
+    fn engine_at_20g() -> Engine {
+        let mut engine = Engine::new(0);
+        engine.set_start_level(18);
+        return engine;
+    }
+
+    #[test]
+    fn spawns_land_without_locking_at_20g() {
+        let mut engine = engine_at_20g();
+        let spawn = *engine.current_shape();
+        assert!(!engine.apply_gravity(1.));
+        assert!(is_resting(&engine));
+        assert_ne!(engine.current_shape().origin(), spawn.origin());
+        assert_eq!(engine.stats.pieces_placed, 0);
+    }
+
+    #[test]
+    fn locks_after_the_lock_delay() {
+        let mut engine = engine_at_20g();
+        engine.apply_gravity(1.);
+        for _ in 1..LOCK_DELAY as usize {
+            assert!(!engine.apply_gravity(1.));
+        }
+        assert!(engine.apply_gravity(1.));
+        assert_eq!(engine.stats.pieces_placed, 1);
+    }
+}
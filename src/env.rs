@@ -0,0 +1,281 @@
+// Gym-style environment around the game engine, to train learning agents
+// against the real rules of the game.
+use crate::{
+    ai::GridAnalysis,
+    bitboard::BitBoard,
+    constants::{GRID_HEIGHT, GRID_WIDTH},
+    engine::Engine,
+    moves::Move,
+    placements::{enumerate_placements, Placement},
+    shapes::ShapePosition,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ActionSpace {
+    // one input (shift, rotation, soft drop or hard drop) per step
+    Inputs,
+    // one step per piece: pick an index in the list of reachable placements
+    Placements,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+    Input(Move),
+    Placement(usize),
+}
+
+// How the reward of each step is computed, as a weighted sum of what happened during the step
+#[derive(Clone, Debug)]
+pub struct RewardConfig {
+    // reward for clearing lines, indexed by the number of lines cleared at once
+    pub line_clears: [f32; 5],
+    // weight of the points scored
+    pub points: f32,
+    // reward for each locked piece (ie for surviving)
+    pub piece_placed: f32,
+    // weight of the change in the number of holes (use a negative weight to punish new holes)
+    pub holes: f32,
+    // weight of the change in stack height (use a negative weight to punish stacking up)
+    pub height: f32,
+    // reward when the game ends (use a negative value to punish topping out)
+    pub game_over: f32,
+}
+
+impl Default for RewardConfig {
+    fn default() -> Self {
+        return RewardConfig {
+            line_clears: [0., 1., 2., 3., 4.],
+            points: 0.,
+            piece_placed: 0.,
+            holes: 0.,
+            height: 0.,
+            game_over: 0.,
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EnvConfig {
+    pub action_space: ActionSpace,
+    pub rewards: RewardConfig,
+    // with the Inputs action space, gravity drops the piece by one cell
+    // every that many steps (0 disables gravity)
+    pub inputs_per_gravity_drop: u32,
+    // episodes are cut short (truncated) after that many pieces
+    pub max_pieces: Option<usize>,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        return EnvConfig {
+            action_space: ActionSpace::Placements,
+            rewards: RewardConfig::default(),
+            inputs_per_gravity_drop: 0,
+            max_pieces: None,
+        };
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EnvObservation {
+    // filled cells of the grid, not including the current piece
+    pub board: [[bool; GRID_WIDTH as usize]; GRID_HEIGHT as usize],
+    pub column_heights: [u8; GRID_WIDTH as usize],
+    pub current_shape: ShapePosition,
+    // upcoming pieces, next one first
+    pub queue: Vec<ShapePosition>,
+    // number of valid actions with the Placements action space
+    pub n_placements: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct StepInfo {
+    pub lines_cleared: i32,
+    pub total_lines_cleared: i32,
+    pub points: i32,
+    pub level: i32,
+    pub pieces_placed: usize,
+    // whether the episode ended because of `max_pieces` rather than a game over
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct StepResult {
+    pub observation: EnvObservation,
+    pub reward: f32,
+    pub done: bool,
+    pub info: StepInfo,
+}
+
+pub struct TetrisEnv {
+    config: EnvConfig,
+    engine: Engine,
+    // placements reachable by the current piece
+    placements: Vec<Placement>,
+    pieces_placed: usize,
+    steps_since_gravity_drop: u32,
+}
+
+impl TetrisEnv {
+    pub fn new(config: EnvConfig) -> Self {
+        let mut env = TetrisEnv {
+            config,
+            engine: Engine::new(0),
+            placements: Vec::new(),
+            pieces_placed: 0,
+            steps_since_gravity_drop: 0,
+        };
+        env.reset(0);
+        return env;
+    }
+
+    pub fn config(&self) -> &EnvConfig {
+        return &self.config;
+    }
+
+    pub fn engine(&self) -> &Engine {
+        return &self.engine;
+    }
+
+    pub fn placements(&self) -> &[Placement] {
+        return &self.placements;
+    }
+
+    pub fn reset(&mut self, seed: u64) -> EnvObservation {
+        self.engine = Engine::new(seed);
+        self.pieces_placed = 0;
+        self.steps_since_gravity_drop = 0;
+        self.update_placements();
+        return self.observation();
+    }
+
+    fn is_done(&self) -> bool {
+        return self.engine.is_game_over() || self.is_truncated();
+    }
+
+    fn is_truncated(&self) -> bool {
+        return self
+            .config
+            .max_pieces
+            .is_some_and(|max_pieces| self.pieces_placed >= max_pieces);
+    }
+
+    // the grid without the piece being played
+    pub fn board(&self) -> BitBoard {
+        let mut board = self.engine.grid().bitboard();
+        board.unset_pixels(&self.engine.current_shape().get_pixels());
+        return board;
+    }
+
+    fn update_placements(&mut self) {
+        self.placements = if self.engine.is_game_over() {
+            Vec::new()
+        } else {
+            enumerate_placements(&self.board(), self.engine.current_shape())
+        };
+    }
+
+    pub fn observation(&self) -> EnvObservation {
+        let board = self.board();
+        let mut cells = [[false; GRID_WIDTH as usize]; GRID_HEIGHT as usize];
+        for i in 0..GRID_HEIGHT {
+            for j in 0..GRID_WIDTH {
+                cells[i as usize][j as usize] = board.is_filled(i, j);
+            }
+        }
+        return EnvObservation {
+            board: cells,
+            column_heights: *board.column_heights(),
+            current_shape: *self.engine.current_shape(),
+            queue: self.engine.preview().to_vec(),
+            n_placements: self.placements.len(),
+        };
+    }
+
+    // returns a bool indicating whether the current piece got locked
+    fn apply(&mut self, action: Action) -> Result<bool, String> {
+        match (self.config.action_space, action) {
+            (ActionSpace::Inputs, Action::Input(move_)) => {
+                if self.engine.apply_move(&move_) {
+                    return Ok(true);
+                }
+                if self.config.inputs_per_gravity_drop > 0 {
+                    self.steps_since_gravity_drop += 1;
+                    if self.steps_since_gravity_drop >= self.config.inputs_per_gravity_drop {
+                        self.steps_since_gravity_drop = 0;
+                        return Ok(self.engine.perform_block_drop());
+                    }
+                }
+                return Ok(false);
+            }
+            (ActionSpace::Placements, Action::Placement(index)) => {
+                let placement = self.placements.get(index).ok_or(format!(
+                    "placement index {} out of range (0..{})",
+                    index,
+                    self.placements.len()
+                ))?;
+                for move_ in placement.moves.clone() {
+                    if self.engine.apply_move(&move_) {
+                        return Ok(true);
+                    }
+                }
+                return Err("placement did not lock the piece".to_string());
+            }
+            (action_space, action) => {
+                return Err(format!(
+                    "action {:?} does not belong to the {:?} action space",
+                    action, action_space
+                ))
+            }
+        }
+    }
+
+    pub fn step(&mut self, action: Action) -> Result<StepResult, String> {
+        if self.is_done() {
+            return Err("the episode is over, call reset first".to_string());
+        }
+        let board_before = self.board();
+        let score_before = self.engine.score.clone();
+
+        let did_lock = self.apply(action)?;
+        if did_lock {
+            self.pieces_placed += 1;
+            self.steps_since_gravity_drop = 0;
+            self.update_placements();
+        }
+
+        let score = &self.engine.score;
+        let lines_cleared = score.total_lines_cleared - score_before.total_lines_cleared;
+        let rewards = &self.config.rewards;
+        let mut reward = rewards.line_clears[lines_cleared as usize]
+            + rewards.points * (score.points - score_before.points) as f32;
+        if did_lock {
+            reward += rewards.piece_placed;
+        }
+        if self.engine.is_game_over() {
+            reward += rewards.game_over;
+        } else if did_lock {
+            // (on game over, the spawned piece overlaps the stack so the board is not meaningful)
+            let board = self.board();
+            let new_holes = GridAnalysis::count_gaps(&board) as f32
+                - GridAnalysis::count_gaps(&board_before) as f32;
+            let height_increase = GridAnalysis::get_first_nonempty_row_index(&board_before) as f32
+                - GridAnalysis::get_first_nonempty_row_index(&board) as f32;
+            reward += rewards.holes * new_holes + rewards.height * height_increase;
+        }
+
+        return Ok(StepResult {
+            observation: self.observation(),
+            reward,
+            done: self.is_done(),
+            info: StepInfo {
+                lines_cleared,
+                total_lines_cleared: score.total_lines_cleared,
+                points: score.points,
+                level: score.level,
+                pieces_placed: self.pieces_placed,
+                truncated: self.is_truncated() && !self.engine.is_game_over(),
+            },
+        });
+    }
+}
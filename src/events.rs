@@ -5,7 +5,7 @@ use std::{time::{SystemTime, Duration}, collections::HashMap};
 // represent this?
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Event {
-    GravityDrop,
+    PieceLocked,
     UserMove,
     GameOver,
 }
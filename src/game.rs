@@ -1,92 +1,240 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use macroquad::{
-    prelude::{KeyCode, WHITE},
+    prelude::{get_char_pressed, is_key_pressed, KeyCode, WHITE},
     text::{draw_text, measure_text},
-    window::{next_frame, screen_height, screen_width},
+    time::get_frame_time,
+    window::{next_frame, screen_width},
 };
 
 use crate::{
-    ai::TetrisBot,
+    agents::Agent,
+    effects::{LineClearAnimation, Particles},
+    engine::Engine,
     events::{Event, EventLog},
-    grid::Grid,
-    moves::{Move, SimpleMove},
+    game_stats::GameStats,
+    gravity::{GravityCurve, FRAMES_PER_SECOND},
+    high_scores::{HighScore, HighScoreTable},
+    modes::{format_time, GameEnd, GameMode},
+    moves::{KeyBindings, Move},
     music::MusicPlayer,
     score::Score,
-    shapes::ShapePosition,
+    versus::{AttackTable, VersusState},
 };
 
 pub struct Game {
-    grid: Grid,
-    // info about the tetromino that the user currently controls
-    current_shape: ShapePosition,
-
-    pub score: Score,
-    bot: Option<TetrisBot>,
+    engine: Engine,
+    bot: Option<Box<dyn Agent>>,
+    key_bindings: KeyBindings,
+    // garbage exchange with an opponent, in a versus match
+    versus: Option<VersusState>,
+    // time at which each multiple of `split_lines()` lines of the mode got cleared
+    splits: Vec<Duration>,
+    // why the game ended, once it did
+    end: Option<GameEnd>,
+    // in no-screen mode, the game does not wait for gravity or for the bot, so its clock
+    // advances by the time it takes to fall by one row at each update, plus the time
+    // between two moves on screen (`USER_MOVE_DEBOUNCE`) for each move of the bot
+    simulated_time: Duration,
+    // when the gravity last acted on the current piece
+    last_gravity_update: Instant,
+    // time the next piece waits after a piece got locked, on top of the line clear animation
+    entry_delay: Duration,
+    // of the last line clear, until the next piece gets to move
+    line_clear_animation: Option<LineClearAnimation>,
+    particles: Particles,
 
     music_player: MusicPlayer,
     event_log: EventLog,
     clock_speedup_rate: u32,
     no_screen: bool,
+    start_time: Instant,
+
+    high_scores: HighScoreTable,
+    // name typed by the player after a high score, until they press enter
+    name_entry: Option<String>,
+    // rank of this game in the high scores, if it made it
+    high_score_rank: Option<usize>,
 }
 
 impl Game {
     const USER_MOVE_DEBOUNCE: Duration = Duration::from_millis(100);
-
-    pub fn new(use_ai: bool, speedup_rate: u32, no_screen: bool) -> Self {
-        let grid = Grid::new();
-        let current_shape = ShapePosition::new();
-        let bot = use_ai.then(|| {
-            let mut _bot = TetrisBot::new();
-            _bot.update_policy(&grid, &current_shape);
-            return _bot;
-        });
-
-        return Game {
-            grid,
-            current_shape,
-            score: Score::new(),
+    const MAX_NAME_LENGTH: usize = 16;
+
+    pub fn new(
+        bot: Option<Box<dyn Agent>>,
+        mode: Box<dyn GameMode>,
+        speedup_rate: u32,
+        no_screen: bool,
+    ) -> Self {
+        let mut game = Game {
+            engine: Engine::with_mode(rand::random(), mode),
             bot,
+            key_bindings: KeyBindings::ARROWS_SPACE,
+            versus: None,
+            splits: Vec::new(),
+            end: None,
+            simulated_time: Duration::ZERO,
+            last_gravity_update: Instant::now(),
+            entry_delay: Duration::ZERO,
+            line_clear_animation: None,
+            particles: Particles::new(),
             music_player: MusicPlayer::new(!no_screen),
             event_log: EventLog::new(),
             clock_speedup_rate: speedup_rate,
             no_screen,
+            start_time: Instant::now(),
+            // (headless runs, eg batches of bot games, stay out of the high scores)
+            high_scores: if no_screen {
+                HighScoreTable::default()
+            } else {
+                HighScoreTable::load()
+            },
+            name_entry: None,
+            high_score_rank: None,
         };
+        game.update_bot_policy();
+        return game;
+    }
+
+    pub fn score(&self) -> &Score {
+        return &self.engine.score;
+    }
+
+    pub fn stats(&self) -> &GameStats {
+        return &self.engine.stats;
+    }
+
+    // stops the music, eg when another game plays its own
+    pub fn mute(&mut self) {
+        self.music_player = MusicPlayer::new(false);
+    }
+
+    pub fn set_gravity_curve(&mut self, gravity_curve: GravityCurve) {
+        self.engine.set_gravity_curve(gravity_curve);
+    }
+
+    // only before the game starts
+    pub fn set_start_level(&mut self, start_level: i32) {
+        self.engine.set_start_level(start_level);
     }
 
-    fn get_shape_pixels(&self) -> [(i16, i16); 4] {
-        return self.current_shape.get_pixels();
+    // also known as ARE
+    pub fn set_entry_delay(&mut self, entry_delay: Duration) {
+        self.entry_delay = entry_delay;
     }
 
-    fn is_valid_move(&self, new_pos: &ShapePosition) -> bool {
-        // check if the current shape can be moved from its current position
-        // to its desired next position
-        let new_pixels = new_pos.get_pixels();
+    pub fn set_key_bindings(&mut self, key_bindings: KeyBindings) {
+        self.key_bindings = key_bindings;
+    }
+
+    pub fn enable_versus(&mut self, attack_table: AttackTable) {
+        self.versus = Some(VersusState::new(attack_table));
+    }
+
+    // lines of garbage sent to the opponent since the last call
+    pub fn take_outgoing_garbage(&mut self) -> u32 {
         return self
-            .grid
-            .can_set_pixels(&new_pixels, &self.get_shape_pixels());
+            .versus
+            .as_mut()
+            .map_or(0, |versus| versus.take_outgoing());
     }
 
-    fn is_valid_add(&self, new_pos: &ShapePosition) -> bool {
-        // check if a new piece can be added to the board (if not, game over)
-        return self.grid.can_set_pixels(&new_pos.get_pixels(), &[]);
+    pub fn receive_garbage(&mut self, lines: u32) {
+        if let Some(versus) = &mut self.versus {
+            versus.receive(lines);
+        }
     }
 
-    fn clear_shape_from_grid(&mut self) {
-        self.grid.unset_pixels(&self.get_shape_pixels());
+    pub fn end(&self) -> Option<GameEnd> {
+        return self.end;
     }
 
-    fn add_shape_to_grid(&mut self) {
-        self.grid.set_pixels(
-            &self.get_shape_pixels(),
-            self.current_shape.color_index as i32,
-        );
+    // rule set of the high scores: games on other gravity curves, or from another
+    // start level, are ranked separately
+    fn rules(&self) -> String {
+        let curve = match self.engine.gravity_curve() {
+            GravityCurve::Guideline => "standard",
+            curve => curve.name(),
+        };
+        let start_level = self.engine.score.start_level;
+        if start_level == 0 {
+            return curve.to_string();
+        }
+        return format!("{}-start{}", curve, start_level);
+    }
+
+    fn is_over(&self) -> bool {
+        return self.event_log.did_happen(Event::GameOver);
+    }
+
+    // time that counts for the time limits of the modes, the splits and the time played
+    // (simulated without a screen, where the game runs as fast as it can)
+    fn clock(&self) -> Duration {
+        if self.no_screen {
+            return self.simulated_time;
+        }
+        return self.start_time.elapsed();
+    }
+
+    // returns a bool indicating whether the game just ended
+    fn check_end_of_game(&mut self) -> bool {
+        let end = self.engine.mode().end_of_game(&self.engine, self.clock());
+        if let Some(end) = end {
+            self.game_over(end);
+            return true;
+        }
+        return false;
+    }
+
+    fn update_time_played(&mut self) {
+        self.engine.stats.time_played = self.clock();
+    }
+
+    fn update_bot_policy(&mut self) {
+        if let Some(bot) = &mut self.bot {
+            bot.update_policy(&self.engine.observation());
+        }
+    }
+
+    // call this function each time the current piece gets locked
+    fn on_piece_locked(&mut self) {
+        self.update_splits();
+        self.start_line_clear_effects();
+        if let (Some(versus), Some(lock)) = (&mut self.versus, self.engine.last_lock()) {
+            versus.on_piece_locked(&lock, &mut self.engine);
+        }
+        if !self.check_end_of_game() {
+            self.update_bot_policy();
+        }
+    }
+
+    fn start_line_clear_effects(&mut self) {
+        if self.no_screen {
+            return;
+        }
+        self.event_log.register_event(Event::PieceLocked);
+        self.line_clear_animation = self.engine.last_line_clear().map(|line_clear| {
+            if line_clear.rows.len() == 4 {
+                self.particles.burst(line_clear);
+            }
+            return LineClearAnimation::new(line_clear.clone(), self.clock_speedup_rate);
+        });
     }
 
-    fn move_shape_to(&mut self, new_pos: ShapePosition) {
-        self.clear_shape_from_grid();
-        self.current_shape = new_pos;
-        self.add_shape_to_grid();
+    // whether the next piece still waits for the line clear animation and the entry delay
+    // (the engine already spawned it, but it does not show nor move yet)
+    fn is_waiting_for_next_piece(&self) -> bool {
+        if self.no_screen || self.is_over() {
+            return false;
+        }
+        let mut delay = self.entry_delay;
+        if self.line_clear_animation.is_some() {
+            delay += LineClearAnimation::DURATION;
+        }
+        return !self
+            .event_log
+            .elapsed_since(Event::PieceLocked, delay / self.clock_speedup_rate);
     }
 
     fn perform_user_move(&mut self) {
@@ -95,6 +243,7 @@ impl Game {
         if self.no_screen {
             while let Some(user_move) = self.get_move_from_human_or_bot() {
                 self.apply_move(&user_move);
+                self.simulated_time += Self::USER_MOVE_DEBOUNCE;
             }
             return;
         }
@@ -116,95 +265,225 @@ impl Game {
         if let Some(bot) = &mut self.bot {
             return bot.pop_next_move();
         } else {
-            return Move::from_key_press();
+            return self.key_bindings.move_pressed();
+        }
+    }
+
+    fn update_splits(&mut self) {
+        let Some(split_lines) = self.engine.mode().split_lines() else {
+            return;
+        };
+        // a tetris can cross a split, but not two
+        let n_splits = self.engine.score.total_lines_cleared / split_lines;
+        if n_splits as usize > self.splits.len() {
+            self.splits.push(self.clock());
         }
     }
 
     fn apply_move(&mut self, move_: &Move) {
-        match move_ {
-            Move::Simple(simple_move) => {
-                let new_pos = self.current_shape.moved_to(&simple_move);
-                if self.is_valid_move(&new_pos) {
-                    self.move_shape_to(new_pos);
-                }
-            }
-            Move::HardDrop => {
-                let mut did_hit_rock_bottom = false;
-                while !did_hit_rock_bottom {
-                    did_hit_rock_bottom = self.perform_block_drop();
-                }
-            }
+        if self.is_over() {
+            return;
+        }
+        if self.engine.apply_move(move_) {
+            self.on_piece_locked();
         }
     }
 
-    fn game_over(&mut self) {
+    fn game_over(&mut self, end: GameEnd) {
+        self.end = Some(end);
+        self.update_time_played();
         self.event_log.register_event(Event::GameOver);
         self.music_player.play_game_over();
+
+        if self.no_screen || !self.engine.mode().counts_for_high_scores(end) {
+            return;
+        }
+        if let Some(bot) = &self.bot {
+            let name = bot.name();
+            self.record_high_score(&name);
+            return;
+        }
+        let run = self.new_high_score("");
+        if self
+            .high_scores
+            .rank_of(self.engine.mode().name(), &self.rules(), false, &run)
+            .is_some()
+        {
+            // drop the keys typed during the game (eg the space of the hard drops)
+            while get_char_pressed().is_some() {}
+            self.name_entry = Some(String::new());
+        }
+    }
+
+    fn new_high_score(&self, name: &str) -> HighScore {
+        let score = &self.engine.score;
+        return HighScore::new(
+            name,
+            score.points,
+            score.total_lines_cleared,
+            score.start_level,
+            score.level,
+            self.engine.stats.time_played.as_secs_f32(),
+        );
+    }
+
+    fn record_high_score(&mut self, name: &str) {
+        let entry = self.new_high_score(name);
+        let mode = self.engine.mode().name();
+        self.high_score_rank = self
+            .high_scores
+            .add(mode, &self.rules(), self.bot.is_some(), entry);
+        if self.high_score_rank.is_some() {
+            if let Err(err) = self.high_scores.save() {
+                eprintln!("Could not save the high scores: {}", err);
+            }
+        }
+    }
+
+    // returns a bool indicating whether the player is done typing their name
+    fn update_name_entry(&mut self) -> bool {
+        let Some(name) = &mut self.name_entry else {
+            return true;
+        };
+        while let Some(c) = get_char_pressed() {
+            let is_leading_space = c == ' ' && name.is_empty();
+            if (c.is_alphanumeric() || c == ' ' || c == '-' || c == '_')
+                && !is_leading_space
+                && name.chars().count() < Self::MAX_NAME_LENGTH
+            {
+                name.push(c);
+            }
+        }
+        if is_key_pressed(KeyCode::Backspace) {
+            name.pop();
+        }
+        if is_key_pressed(KeyCode::Enter) && !name.trim().is_empty() {
+            let name = name.trim().to_string();
+            self.name_entry = None;
+            self.record_high_score(&name);
+            // leave some time to see the rank
+            self.event_log.register_event(Event::GameOver);
+            return true;
+        }
+        return false;
+    }
+
+    // time of each split, and time since the previous one
+    fn split_lines(&self) -> Vec<String> {
+        let Some(split_lines) = self.engine.mode().split_lines() else {
+            return Vec::new();
+        };
+        let mut previous = Duration::ZERO;
+        let mut lines = Vec::new();
+        for (i, &split) in self.splits.iter().enumerate() {
+            lines.push(format!(
+                "{} lines: {} (+{})",
+                (i as i32 + 1) * split_lines,
+                format_time(split),
+                format_time(split - previous)
+            ));
+            previous = split;
+        }
+        return lines;
     }
 
     fn draw_game_over_screen(&self) {
-        let message = "Game over";
+        let message = match self.end {
+            Some(GameEnd::GoalReached) => {
+                format!("Finished in {}", format_time(self.engine.stats.time_played))
+            }
+            Some(GameEnd::TimeUp) => format!("Time's up: {} points", self.engine.score.points),
+            _ => "Game over".to_string(),
+        };
         let font_size = 60;
-        let text_dims = measure_text(message, None, font_size, 1.);
+        let text_dims = measure_text(&message, None, font_size, 1.);
+        // the statistics take most of the screen
+        let top = 60.;
         draw_text(
-            message,
+            &message,
             (screen_width() - text_dims.width) / 2.,
-            (screen_height() - text_dims.height) / 2.,
+            top,
             font_size as f32,
             WHITE,
         );
+        // the statistics are wider than the message
+        let left = f32::max(10., screen_width() / 2. - 250.);
+        let mut line_top = top + 50.;
+        for line in self.split_lines() {
+            draw_text(&line, left, line_top, 30., WHITE);
+            line_top += 32.;
+        }
+        self.engine.stats.draw(left, line_top);
+
+        line_top += self.engine.stats.to_lines().len() as f32 * 32. + 20.;
+        if let Some(name) = &self.name_entry {
+            draw_text(
+                &format!("New high score! Your name: {}_", name),
+                left,
+                line_top,
+                30.,
+                WHITE,
+            );
+        } else if let Some(rank) = self.high_score_rank {
+            draw_text(
+                &format!("High score #{}", rank + 1),
+                left,
+                line_top,
+                30.,
+                WHITE,
+            );
+        }
     }
 
-    fn perform_block_drop(&mut self) -> bool {
-        // try to move the current piece one cell down
-        let mut new_pos = self.current_shape.moved_to(&SimpleMove::Down);
-        if !self.is_valid_move(&new_pos) {
-            // we can't move down any further: issue a new piece
-            // first, check if any line got cleared
-            let n_cleared = self.grid.clear_completed_rows();
-            self.score.update(n_cleared);
-
-            // then drop the next piece
-            new_pos = ShapePosition::new();
-            if !self.is_valid_add(&new_pos) {
-                // show the piece overlap with existing pieces
-                // for dramatic effect
-                self.current_shape = new_pos;
-                self.add_shape_to_grid();
-                self.game_over();
-                return true;
-            }
-            self.current_shape = new_pos;
-            self.add_shape_to_grid();
-
-            if let Some(bot) = &mut self.bot {
-                bot.update_policy(&self.grid, &self.current_shape)
-            }
-
-            return true;
+    // lets the gravity act for the frames since the last update
+    fn perform_gravity(&mut self) {
+        let frames = if self.no_screen {
+            // one row at a time, or one frame when it falls faster than that
+            f64::max(1. / self.engine.gravity(), 1.)
         } else {
-            self.move_shape_to(new_pos);
-            return false;
+            self.last_gravity_update.elapsed().as_secs_f64()
+                * FRAMES_PER_SECOND
+                * self.clock_speedup_rate as f64
+        };
+        self.last_gravity_update = Instant::now();
+        if self.no_screen {
+            self.simulated_time += Duration::from_secs_f64(frames / FRAMES_PER_SECOND);
         }
-    }
-
-    fn perform_block_drop_debounced(&mut self) {
-        if !self.no_screen && !self.event_log.elapsed_since(
-            Event::GravityDrop,
-            self.score.get_block_drop_delay() / self.clock_speedup_rate,
-        ) {
-            return;
+        if self.engine.apply_gravity(frames) {
+            self.on_piece_locked();
         }
-        self.perform_block_drop();
-        self.event_log.register_event(Event::GravityDrop);
     }
 
-    fn draw(&self) {
+    pub fn draw(&self) {
         if self.no_screen {
             return;
         }
-        self.grid.draw();
-        self.score.draw();
+        if !self.is_waiting_for_next_piece() {
+            self.engine.grid().draw();
+        } else if let Some(animation) = &self.line_clear_animation {
+            animation.draw();
+        } else {
+            let mut grid = self.engine.grid().clone();
+            grid.unset_pixels(&self.engine.current_shape().get_pixels());
+            grid.draw();
+        }
+        self.particles.draw();
+        self.engine.score.draw();
+        if let Some(time_limit) = self.engine.mode().time_limit() {
+            let time_left = time_limit.saturating_sub(self.clock());
+            self.engine
+                .score
+                .draw_text_at(&format!("Time left: {}", format_time(time_left)), 3);
+        } else if self.engine.mode().is_ranked_by_time() {
+            let time = format_time(self.clock());
+            self.engine
+                .score
+                .draw_text_at(&format!("Time: {}", time), 3);
+        }
+        self.engine.next_shapes().draw();
+        if let Some(versus) = &self.versus {
+            versus.draw_meter();
+        }
     }
 
     pub async fn play(&mut self) {
@@ -217,20 +496,27 @@ impl Game {
                 next_frame().await;
             }
 
-            if macroquad::prelude::is_key_down(KeyCode::Q) {
+            // (Q may also be part of a high score name)
+            if self.name_entry.is_none() && macroquad::prelude::is_key_down(KeyCode::Q) {
                 break;
             }
         }
+        if !self.is_over() {
+            self.update_time_played();
+        }
     }
 
     // returns a bool indicating whether the game should keep going
-    fn update_game(&mut self) -> bool {
+    pub fn update_game(&mut self) -> bool {
         if self.event_log.did_happen(Event::GameOver) {
             if self.no_screen {
                 return false;
             }
             self.draw_game_over_screen();
 
+            if !self.update_name_entry() {
+                return true;
+            }
             if self
                 .event_log
                 .elapsed_since(Event::GameOver, Duration::from_secs(3))
@@ -240,11 +526,27 @@ impl Game {
             return true;
         }
 
+        // the time limit of the mode may have run out
+        if self.check_end_of_game() {
+            return true;
+        }
+
+        if !self.no_screen {
+            self.particles.update(get_frame_time());
+        }
+        if self.is_waiting_for_next_piece() {
+            // the gravity starts over once the piece shows up
+            self.last_gravity_update = Instant::now();
+            self.draw();
+            return true;
+        }
+        self.line_clear_animation = None;
+
         // honor user-requested move if any
         self.perform_user_move();
 
-        // move current block one step down
-        self.perform_block_drop_debounced();
+        // move current block down, by as many rows as the gravity requires
+        self.perform_gravity();
 
         self.draw();
         return true;
@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use macroquad::{prelude::WHITE, text::draw_text};
+
+use crate::{
+    ai::GridAnalysis,
+    bitboard::BitBoard,
+    constants::{GRID_HEIGHT, GRID_WIDTH},
+    shapes::ShapePosition,
+};
+
+// index of the T piece in `get_shapes`
+const T_SHAPE_INDEX: usize = 2;
+
+// A T piece locked right after a rotation, with at least 3 of the 4 cells diagonal to
+// its center occupied (the walls and the floor count as occupied)
+pub fn is_t_spin(board: &BitBoard, shape: &ShapePosition, last_move_was_rotation: bool) -> bool {
+    if !last_move_was_rotation || shape.shape_index() != T_SHAPE_INDEX {
+        return false;
+    }
+    // (the T turns around its center, whatever the rotation system)
+    let (center_i, center_j) = shape.rotation_center();
+    let n_occupied_corners = [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+        .iter()
+        .filter(|(di, dj)| {
+            let (i, j) = (center_i + di, center_j + dj);
+            let is_outside = !(0..GRID_HEIGHT).contains(&i) || !(0..GRID_WIDTH).contains(&j);
+            return is_outside || board.is_filled(i, j);
+        })
+        .count();
+    return n_occupied_corners >= 3;
+}
+
+// Detailed statistics of a game, on top of its `Score`
+#[derive(Clone, Debug)]
+pub struct GameStats {
+    pub pieces_placed: usize,
+    // number of pieces that cleared 1, 2, 3 and 4 lines at once
+    pub line_clears: [usize; 4],
+    pub t_spins: usize,
+    // longest run of consecutive pieces that each cleared lines
+    pub max_combo: usize,
+    combo: usize,
+    // inputs played, by a human or a bot (gravity not included)
+    pub keys_pressed: usize,
+    // holes covered by the pieces (holes uncovered by line clears are not deducted)
+    pub holes_created: usize,
+    // highest column reached when a piece got locked
+    pub max_stack_height: usize,
+    pub time_played: Duration,
+}
+
+impl GameStats {
+    const FONT_SIZE: f32 = 30.;
+    const MARGIN_BETWEEN_STATS: f32 = 32.;
+
+    pub fn new() -> Self {
+        return GameStats {
+            pieces_placed: 0,
+            line_clears: [0; 4],
+            t_spins: 0,
+            max_combo: 0,
+            combo: 0,
+            keys_pressed: 0,
+            holes_created: 0,
+            max_stack_height: 0,
+            time_played: Duration::ZERO,
+        };
+    }
+
+    pub fn on_key_pressed(&mut self) {
+        self.keys_pressed += 1;
+    }
+
+    // `board_before` does not contain the locked piece, and `board_locked` contains it
+    // (before the completed lines get cleared)
+    pub fn on_piece_locked(
+        &mut self,
+        n_lines_cleared: i32,
+        is_t_spin: bool,
+        board_before: &BitBoard,
+        board_locked: &BitBoard,
+    ) {
+        self.pieces_placed += 1;
+        if is_t_spin {
+            self.t_spins += 1;
+        }
+        if n_lines_cleared > 0 {
+            self.line_clears[n_lines_cleared as usize - 1] += 1;
+            self.combo += 1;
+            self.max_combo = usize::max(self.max_combo, self.combo);
+        } else {
+            self.combo = 0;
+        }
+        // (a piece tucked under an overhang fills holes rather than creating some)
+        self.holes_created += GridAnalysis::count_gaps(board_locked)
+            .saturating_sub(GridAnalysis::count_gaps(board_before));
+        let stack_height = *board_locked.column_heights().iter().max().unwrap_or(&0);
+        self.max_stack_height = usize::max(self.max_stack_height, stack_height as usize);
+    }
+
+    pub fn pieces_per_second(&self) -> f32 {
+        if self.time_played.is_zero() {
+            return 0.;
+        }
+        return self.pieces_placed as f32 / self.time_played.as_secs_f32();
+    }
+
+    pub fn keys_per_piece(&self) -> f32 {
+        return self.keys_pressed as f32 / self.pieces_placed.max(1) as f32;
+    }
+
+    // one line of text per statistic
+    pub fn to_lines(&self) -> Vec<String> {
+        return vec![
+            format!("Pieces placed: {}", self.pieces_placed),
+            format!(
+                "Singles / doubles / triples / tetrises: {} / {} / {} / {}",
+                self.line_clears[0], self.line_clears[1], self.line_clears[2], self.line_clears[3]
+            ),
+            format!("T-spins: {}", self.t_spins),
+            format!("Max combo: {}", self.max_combo),
+            format!("Pieces per second: {:.2}", self.pieces_per_second()),
+            format!("Keys per piece: {:.2}", self.keys_per_piece()),
+            format!("Holes created: {}", self.holes_created),
+            format!("Max stack height: {}", self.max_stack_height),
+            format!("Time played: {:.1}s", self.time_played.as_secs_f32()),
+        ];
+    }
+
+    pub fn draw(&self, left: f32, top: f32) {
+        for (i, line) in self.to_lines().iter().enumerate() {
+            draw_text(
+                line,
+                left,
+                top + i as f32 * Self::MARGIN_BETWEEN_STATS,
+                Self::FONT_SIZE,
+                WHITE,
+            );
+        }
+    }
+}
+
+impl Default for GameStats {
+    fn default() -> Self {
+        return GameStats::new();
+    }
+}
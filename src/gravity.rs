@@ -0,0 +1,154 @@
+// How fast the pieces fall, in rows per frame at 60 frames per second.
+// Above 1G, a piece falls by several rows in a single frame, up to 20G where it
+// reaches the bottom of the grid as soon as it spawns.
+use crate::constants::GRID_HEIGHT;
+
+pub const FRAMES_PER_SECOND: f64 = 60.;
+// "20G": a piece falls the whole height of the grid within one frame
+pub const MAX_GRAVITY: f64 = GRID_HEIGHT as f64;
+// frames a piece can rest on the stack before it locks, so that it can still be moved at 20G
+pub const LOCK_DELAY: f64 = 30.;
+// moves that start the lock delay over, for each piece (so that it can't be kept from locking)
+pub const MAX_LOCK_RESETS: u32 = 15;
+
+// how the gravity increases with the level
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GravityCurve {
+    // https://tetris.wiki/Marathon, reaching 20G at level 18
+    Guideline,
+    // frames per row of NES Tetris, that never goes beyond 1G
+    Nes,
+    // Tetris The Grand Master, slow for many levels, then quickly up to 20G
+    Tgm,
+}
+
+pub const GRAVITY_CURVE_NAMES: [&str; 3] = ["guideline", "nes", "tgm"];
+
+pub fn gravity_curve_from_name(name: &str) -> Result<GravityCurve, String> {
+    return match name {
+        "guideline" => Ok(GravityCurve::Guideline),
+        "nes" => Ok(GravityCurve::Nes),
+        "tgm" => Ok(GravityCurve::Tgm),
+        _ => Err(format!(
+            "unknown gravity curve '{}', expected one of {:?}",
+            name, GRAVITY_CURVE_NAMES
+        )),
+    };
+}
+
+// frames per row at each level, from level 0 (1 frame from level 29 on)
+const NES_FRAMES_PER_ROW: [u32; 29] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6, 5, 5, 5, 4, 4, 4, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+];
+
+// (level from which it applies, gravity in 1/256 rows per frame), for TGM levels from 0 to 999
+const TGM_GRAVITY: [(i32, u32); 34] = [
+    (0, 4),
+    (30, 6),
+    (35, 8),
+    (40, 10),
+    (50, 12),
+    (60, 16),
+    (70, 32),
+    (80, 48),
+    (90, 64),
+    (100, 80),
+    (110, 96),
+    (120, 112),
+    (130, 128),
+    (140, 144),
+    (150, 4),
+    (160, 32),
+    (170, 64),
+    (180, 96),
+    (190, 128),
+    (200, 144),
+    (220, 4),
+    (230, 32),
+    (233, 64),
+    (236, 96),
+    (239, 128),
+    (243, 160),
+    (247, 192),
+    (251, 224),
+    (300, 256),
+    (330, 512),
+    (360, 768),
+    (400, 1024),
+    (420, 1280),
+    (450, 1024),
+];
+// TGM level from which the gravity is 20G
+const TGM_20G_LEVEL: i32 = 500;
+// a TGM level goes up with each piece and each line, so it is about 10 times ours
+const TGM_LEVELS_PER_LEVEL: i32 = 10;
+
+impl GravityCurve {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            GravityCurve::Guideline => "guideline",
+            GravityCurve::Nes => "nes",
+            GravityCurve::Tgm => "tgm",
+        };
+    }
+
+    // rows per frame at a level (from 0), at most 20G
+    pub fn rows_per_frame(&self, level: i32) -> f64 {
+        let level = level.max(0);
+        let rows_per_frame = match self {
+            GravityCurve::Guideline => {
+                // the levels of the guideline start at 1
+                let level = level + 1;
+                let seconds_per_row = f64::powi(0.8 - (level as f64 - 1.) * 0.007, level - 1);
+                1. / (seconds_per_row * FRAMES_PER_SECOND)
+            }
+            GravityCurve::Nes => {
+                let frames = NES_FRAMES_PER_ROW.get(level as usize).unwrap_or(&1);
+                1. / *frames as f64
+            }
+            GravityCurve::Tgm => {
+                let tgm_level = level * TGM_LEVELS_PER_LEVEL;
+                if tgm_level >= TGM_20G_LEVEL {
+                    MAX_GRAVITY
+                } else {
+                    let (_, gravity) = TGM_GRAVITY
+                        .iter()
+                        .rev()
+                        .find(|(from_level, _)| *from_level <= tgm_level)
+                        .unwrap();
+                    *gravity as f64 / 256.
+                }
+            }
+        };
+        // (the guideline formula goes negative past level 100 or so)
+        if rows_per_frame.is_nan() || rows_per_frame <= 0. {
+            return MAX_GRAVITY;
+        }
+        return rows_per_frame.min(MAX_GRAVITY);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nes_level_0_falls_a_row_every_48_frames() {
+        assert_eq!(GravityCurve::Nes.rows_per_frame(0), 1. / 48.);
+        assert_eq!(GravityCurve::Nes.rows_per_frame(29), 1.);
+    }
+
+    #[test]
+    fn guideline_reaches_20g_at_level_18() {
+        assert!(GravityCurve::Guideline.rows_per_frame(17) < MAX_GRAVITY);
+        assert_eq!(GravityCurve::Guideline.rows_per_frame(18), MAX_GRAVITY);
+    }
+
+    #[test]
+    fn tgm_reaches_20g_at_level_50() {
+        assert!(GravityCurve::Tgm.rows_per_frame(49) < MAX_GRAVITY);
+        for level in [50, 100, 1000] {
+            assert_eq!(GravityCurve::Tgm.rows_per_frame(level), MAX_GRAVITY);
+        }
+    }
+}
@@ -1,28 +1,38 @@
 use macroquad::{
-    prelude::BLUE,
+    prelude::{Color, BLUE, GRAY, WHITE},
     shapes::{draw_line, draw_rectangle},
 };
 
 use crate::{
+    bitboard::{BitBoard, FULL_ROW},
     constants::{BLOCK_SIZE, CELL_BORDER, GRID_HEIGHT, GRID_WIDTH},
     shapes::SHAPE_COLORS,
 };
 
 pub const EMPTY_CELL: i32 = -1;
+// cells of the garbage rows, that do not come from a piece
+pub const GARBAGE_CELL: i32 = -2;
 
 #[derive(Clone)]
 pub struct Grid {
-    // a 2D array where each cell represents a cell on the Tetris grid
-    pub grid: [[i32; GRID_WIDTH as usize]; GRID_HEIGHT as usize],
+    // which cells are occupied
+    cells: BitBoard,
+    // a 2D array with the color of each cell on the Tetris grid (only used for rendering)
+    colors: [[i32; GRID_WIDTH as usize]; GRID_HEIGHT as usize],
 }
 
 impl Grid {
     pub fn new() -> Self {
         Grid {
-            grid: [[EMPTY_CELL; GRID_WIDTH as usize]; GRID_HEIGHT as usize],
+            cells: BitBoard::new(),
+            colors: [[EMPTY_CELL; GRID_WIDTH as usize]; GRID_HEIGHT as usize],
         }
     }
 
+    pub fn bitboard(&self) -> BitBoard {
+        return self.cells;
+    }
+
     pub fn can_set_pixels(
         &self,
         pixels_to_set: &[(i16, i16)],
@@ -38,7 +48,7 @@ impl Grid {
                 // ignore it
                 continue;
             }
-            if self.grid[*i as usize][*j as usize] != EMPTY_CELL {
+            if self.cells.is_filled(*i, *j) {
                 // cell is occupied
                 return false;
             }
@@ -48,7 +58,12 @@ impl Grid {
 
     pub fn set_pixels(&mut self, pixels: &[(i16, i16)], color: i32) {
         for (i, j) in pixels {
-            self.grid[*i as usize][*j as usize] = color;
+            self.colors[*i as usize][*j as usize] = color;
+        }
+        if color == EMPTY_CELL {
+            self.cells.unset_pixels(pixels);
+        } else {
+            self.cells.set_pixels(pixels);
         }
     }
 
@@ -59,40 +74,98 @@ impl Grid {
     pub fn clear_completed_rows(&mut self) -> i32 {
         // We use naive gravity and support split line clears
         // See https://tetris.wiki/Line_clear
-        let mut n_cleared = 0;
-        for i in 0..(GRID_HEIGHT as usize) {
-            if self.grid[i].iter().all(|&x| x != EMPTY_CELL) {
-                self.shift_rows_down(i);
-                n_cleared += 1;
-            }
+        for i in self.cells.completed_rows() {
+            self.shift_rows_down(i);
+        }
+        return self.cells.clear_completed_rows();
+    }
+
+    // pushes the stack up, adding one garbage row at the bottom for each hole column
+    // (the last one at the very bottom).
+    // returns a bool indicating whether some filled cells got pushed out of the top
+    pub fn add_garbage_rows(&mut self, holes: &[i16]) -> bool {
+        let rows: Vec<u16> = holes.iter().map(|&hole| FULL_ROW & !(1 << hole)).collect();
+        let is_topped_out = self.cells.push_rows_up(&rows);
+
+        let n = holes.len().min(GRID_HEIGHT as usize);
+        self.colors.copy_within(n.., 0);
+        let first_row = GRID_HEIGHT as usize - n;
+        for (i, &hole) in holes[holes.len() - n..].iter().enumerate() {
+            let mut row = [GARBAGE_CELL; GRID_WIDTH as usize];
+            row[hole as usize] = EMPTY_CELL;
+            self.colors[first_row + i] = row;
         }
-        return n_cleared;
+        return is_topped_out;
+    }
+
+    // rows that still contain some garbage
+    pub fn count_garbage_rows(&self) -> usize {
+        return self
+            .colors
+            .iter()
+            .filter(|row| row.contains(&GARBAGE_CELL))
+            .count();
     }
 
     fn shift_rows_down(&mut self, start_index: usize) {
         let mut i = start_index;
         while i > 0 {
-            self.grid[i] = self.grid[i - 1];
+            self.colors[i] = self.colors[i - 1];
             i -= 1;
         }
-        self.grid[0] = [EMPTY_CELL; GRID_WIDTH as usize];
+        self.colors[0] = [EMPTY_CELL; GRID_WIDTH as usize];
+    }
+
+    // color in which a cell is drawn, if it is filled
+    pub fn cell_color(&self, i: i16, j: i16) -> Option<Color> {
+        return match self.colors[i as usize][j as usize] {
+            EMPTY_CELL => None,
+            GARBAGE_CELL => Some(GRAY),
+            color_index => Some(SHAPE_COLORS[color_index as usize]),
+        };
+    }
+
+    fn draw_cell(i: i16, j: i16, color: Color) {
+        draw_rectangle(
+            (j * BLOCK_SIZE) as f32 + CELL_BORDER,
+            (i * BLOCK_SIZE) as f32 + CELL_BORDER,
+            BLOCK_SIZE as f32 - CELL_BORDER,
+            BLOCK_SIZE as f32 - CELL_BORDER,
+            color,
+        );
     }
 
     pub fn draw(&self) {
+        self.draw_clearing(&[], 0.);
+    }
+
+    // Draws the grid while the given rows get cleared, `progress` going from 0 to 1:
+    // the rows flash white, then dissolve from the center outwards.
+    pub fn draw_clearing(&self, cleared_rows: &[usize], progress: f32) {
+        let center = (GRID_WIDTH - 1) as f32 / 2.;
         for i in 0..GRID_HEIGHT {
             // stops at HEIGHT-1
+            let is_cleared = cleared_rows.contains(&(i as usize));
             for j in 0..GRID_WIDTH {
                 // stops at WIDTH-1
-                let pixel_color = self.grid[i as usize][j as usize];
-                if pixel_color != EMPTY_CELL {
-                    draw_rectangle(
-                        (j * BLOCK_SIZE) as f32 + CELL_BORDER,
-                        (i * BLOCK_SIZE) as f32 + CELL_BORDER,
-                        BLOCK_SIZE as f32 - CELL_BORDER,
-                        BLOCK_SIZE as f32 - CELL_BORDER,
-                        SHAPE_COLORS[pixel_color as usize],
+                let Some(mut color) = self.cell_color(i, j) else {
+                    continue;
+                };
+                if is_cleared {
+                    let distance_to_center = (j as f32 - center).abs() / (center + 1.);
+                    if distance_to_center < progress {
+                        continue;
+                    }
+                    // the flash fades out over the first half
+                    let flash = (1. - 2. * progress).max(0.);
+                    color = Color::new(
+                        color.r + (WHITE.r - color.r) * flash,
+                        color.g + (WHITE.g - color.g) * flash,
+                        color.b + (WHITE.b - color.b) * flash,
+                        color.a,
                     );
                 }
+                Self::draw_cell(i, j, color);
             }
         }
 
@@ -0,0 +1,216 @@
+// Best runs, kept in a JSON file in the user's data directory.
+// There is one table per game mode, rule set and kind of player (human or bot).
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use macroquad::{prelude::WHITE, text::draw_text};
+use serde::{Deserialize, Serialize};
+
+use crate::modes::{format_time, mode_from_name};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HighScore {
+    pub name: String,
+    pub points: i32,
+    pub lines_cleared: i32,
+    // (0 for the entries saved before the start level could be picked)
+    #[serde(default)]
+    pub start_level: i32,
+    pub level: i32,
+    pub seconds: f32,
+    // when the game was played, in seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl HighScore {
+    pub fn new(
+        name: &str,
+        points: i32,
+        lines_cleared: i32,
+        start_level: i32,
+        level: i32,
+        seconds: f32,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        return HighScore {
+            name: name.to_string(),
+            points,
+            lines_cleared,
+            start_level,
+            level,
+            seconds,
+            timestamp,
+        };
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct HighScoreTable {
+    // best first, keyed by `category_key`
+    tables: BTreeMap<String, Vec<HighScore>>,
+}
+
+impl HighScoreTable {
+    const MAX_ENTRIES: usize = 10;
+    const FONT_SIZE: f32 = 30.;
+    const MARGIN_BETWEEN_ROWS: f32 = 32.;
+
+    fn category_key(mode: &str, rules: &str, by_bot: bool) -> String {
+        return format!(
+            "{}/{}/{}",
+            mode,
+            rules,
+            if by_bot { "bot" } else { "human" }
+        );
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        return dirs::data_dir().map(|dir| dir.join("tetrus").join("high_scores.json"));
+    }
+
+    // an empty table if there is no file yet (or if it can't be read)
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        return serde_json::from_str(&contents).unwrap_or_else(|err| {
+            eprintln!("Ignoring unreadable high scores in {:?}: {}", path, err);
+            return Self::default();
+        });
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no user data directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        return fs::write(path, serde_json::to_string_pretty(self)?);
+    }
+
+    pub fn entries(&self, mode: &str, rules: &str, by_bot: bool) -> &[HighScore] {
+        return self
+            .tables
+            .get(&Self::category_key(mode, rules, by_bot))
+            .map_or(&[], |entries| entries.as_slice());
+    }
+
+    fn is_ranked_by_time(mode: &str) -> bool {
+        return mode_from_name(mode).is_ok_and(|mode| mode.is_ranked_by_time());
+    }
+
+    // rank (from 0) that a run would get, if it makes it into the table
+    pub fn rank_of(&self, mode: &str, rules: &str, by_bot: bool, run: &HighScore) -> Option<usize> {
+        let entries = self.entries(mode, rules, by_bot);
+        let by_time = Self::is_ranked_by_time(mode);
+        // ties go to the older run
+        let rank = entries
+            .iter()
+            .take_while(|entry| {
+                if by_time {
+                    return entry.seconds <= run.seconds;
+                }
+                return entry.points >= run.points;
+            })
+            .count();
+        return (rank < Self::MAX_ENTRIES).then_some(rank);
+    }
+
+    // returns the rank of the new entry, if it made it into the table
+    pub fn add(
+        &mut self,
+        mode: &str,
+        rules: &str,
+        by_bot: bool,
+        entry: HighScore,
+    ) -> Option<usize> {
+        let rank = self.rank_of(mode, rules, by_bot, &entry)?;
+        let entries = self
+            .tables
+            .entry(Self::category_key(mode, rules, by_bot))
+            .or_default();
+        entries.insert(rank, entry);
+        entries.truncate(Self::MAX_ENTRIES);
+        return Some(rank);
+    }
+
+    pub fn to_lines(&self, mode: &str, rules: &str, by_bot: bool) -> Vec<String> {
+        let entries = self.entries(mode, rules, by_bot);
+        if entries.is_empty() {
+            return vec!["No high score yet".to_string()];
+        }
+        let by_time = Self::is_ranked_by_time(mode);
+        return entries
+            .iter()
+            .enumerate()
+            .map(|(rank, entry)| {
+                if by_time {
+                    return format!(
+                        "{:>2}. {:<16} {:>9}  {:>4} lines",
+                        rank + 1,
+                        entry.name,
+                        format_time(Duration::from_secs_f32(entry.seconds)),
+                        entry.lines_cleared
+                    );
+                }
+                return format!(
+                    "{:>2}. {:<16} {:>8}  {:>4} lines  level {:>2}",
+                    rank + 1,
+                    entry.name,
+                    entry.points,
+                    entry.lines_cleared,
+                    entry.level
+                );
+            })
+            .collect();
+    }
+
+    // every table, for the terminal
+    pub fn print(&self) {
+        if self.tables.is_empty() {
+            println!("No high score yet");
+        }
+        for (category, entries) in &self.tables {
+            println!("{}:", category);
+            for (rank, entry) in entries.iter().enumerate() {
+                println!(
+                    "{:>2}. {:<16} {:>8}  {:>4} lines  level {:>2}  {:.1}s",
+                    rank + 1,
+                    entry.name,
+                    entry.points,
+                    entry.lines_cleared,
+                    entry.level,
+                    entry.seconds
+                );
+            }
+        }
+    }
+
+    pub fn draw(&self, mode: &str, rules: &str, by_bot: bool, left: f32, top: f32) {
+        let title = format!(
+            "High scores: {} ({}, {})",
+            mode,
+            rules,
+            if by_bot { "bots" } else { "humans" }
+        );
+        let lines = std::iter::once(title).chain(self.to_lines(mode, rules, by_bot));
+        for (i, line) in lines.enumerate() {
+            draw_text(
+                &line,
+                left,
+                top + i as f32 * Self::MARGIN_BETWEEN_ROWS,
+                Self::FONT_SIZE,
+                WHITE,
+            );
+        }
+    }
+}
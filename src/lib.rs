@@ -0,0 +1,27 @@
+pub mod agents;
+pub mod ai;
+pub mod benchmark;
+pub mod bitboard;
+pub mod constants;
+pub mod effects;
+pub mod engine;
+pub mod env;
+pub mod events;
+pub mod game;
+pub mod game_stats;
+pub mod gravity;
+pub mod grid;
+pub mod high_scores;
+pub mod menu;
+pub mod modes;
+pub mod moves;
+pub mod music;
+pub mod netplay;
+pub mod placements;
+pub mod score;
+pub mod shapes;
+pub mod stats;
+pub mod tbp;
+pub mod versus;
+#[cfg(feature = "python")]
+mod python;
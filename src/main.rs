@@ -1,20 +1,23 @@
-mod ai;
-mod constants;
-mod events;
-mod game;
-mod grid;
-mod moves;
-mod music;
-mod score;
-mod shapes;
-mod stats;
-
-use game::Game;
+use std::time::Duration;
+
 use macroquad::window::{screen_height, screen_width};
 
 use rustop::opts;
 
-use crate::score::Score;
+use tetrus::{
+    agents::{agent_from_name, AgentConfig},
+    ai::SearchConfig,
+    game::Game,
+    game_stats::GameStats,
+    gravity::{gravity_curve_from_name, GravityCurve},
+    high_scores::HighScoreTable,
+    menu::{Menu, MenuChoice, Settings},
+    modes::{check_start_level, mode_from_name},
+    netplay::{play_online, NetRole},
+    score::Score,
+    stats,
+    versus::{play_match, AttackTable},
+};
 
 // macroquad docs:
 // https://macroquad.rs/examples/
@@ -30,42 +33,253 @@ TODO
 - add sound effect when clearing line
 - try WASM?
 - try to train a self-learning agent
-- teach bot about fall + shift
-- mode with no rendering and no time for bot training/testing
 - find more efficient way of loading the music
  */
 
+// options of the single player games
+struct GameOptions {
+    start_level: i32,
+    gravity_curve: GravityCurve,
+    // before each new piece appears
+    entry_delay: Duration,
+}
+
+// plays `n_games` games in a row, by a human if there is no bot
+async fn play_games(
+    mode_name: &str,
+    n_games: usize,
+    agent: Option<(&str, &AgentConfig)>,
+    options: &GameOptions,
+    speedup: u32,
+    no_screen: bool,
+) -> Result<(Vec<Score>, Vec<GameStats>), String> {
+    let mut scores: Vec<Score> = Vec::new();
+    let mut game_stats: Vec<GameStats> = Vec::new();
+    for i in 0..n_games {
+        println!("Game {}/{}", i + 1, n_games);
+        let bot = match agent {
+            Some((name, agent_config)) => Some(agent_from_name(name, agent_config)?),
+            None => None,
+        };
+        let mode = mode_from_name(mode_name)?;
+        let mut game = Game::new(bot, mode, speedup, no_screen);
+        game.set_start_level(options.start_level);
+        game.set_gravity_curve(options.gravity_curve);
+        game.set_entry_delay(options.entry_delay);
+        game.play().await;
+        scores.push(game.score().clone());
+        game_stats.push(game.stats().clone());
+    }
+    return Ok((scores, game_stats));
+}
+
+// a human against another human, or against a bot
+async fn play_versus(
+    agent: Option<(&str, &AgentConfig)>,
+    attack_table: &AttackTable,
+) -> Result<(), String> {
+    let bot = match agent {
+        Some((name, agent_config)) => Some(agent_from_name(name, agent_config)?),
+        None => None,
+    };
+    match play_match([None, bot], attack_table).await {
+        Some(winner) => println!("Player {} wins", winner + 1),
+        None => println!("No winner"),
+    }
+    return Ok(());
+}
+
+fn print_summary(scores: &[Score], game_stats: &[GameStats]) {
+    println!("Summary stats over {} games:", scores.len());
+    let n_points: Vec<i32> = scores.iter().map(|s| s.points).collect();
+    let n_lines_cleared: Vec<i32> = scores.iter().map(|s| s.total_lines_cleared).collect();
+    let level: Vec<i32> = scores.iter().map(|s| s.level).collect();
+    println!("Score: {}", stats::summarize(&n_points));
+    println!(
+        "Number of lines cleared: {}",
+        stats::summarize(&n_lines_cleared)
+    );
+    println!("Average level attained: {}", stats::summarize(&level));
+    let summarize_stat = |name: &str, stat: fn(&GameStats) -> f32| {
+        let vals: Vec<f32> = game_stats.iter().map(stat).collect();
+        println!("{}: {}", name, stats::float_summarize(&vals));
+    };
+    summarize_stat("Pieces placed", |s| s.pieces_placed as f32);
+    summarize_stat("Tetrises", |s| s.line_clears[3] as f32);
+    summarize_stat("T-spins", |s| s.t_spins as f32);
+    summarize_stat("Max combo", |s| s.max_combo as f32);
+    summarize_stat("Pieces per second", GameStats::pieces_per_second);
+    summarize_stat("Keys per piece", GameStats::keys_per_piece);
+    summarize_stat("Holes created", |s| s.holes_created as f32);
+    summarize_stat("Max stack height", |s| s.max_stack_height as f32);
+    summarize_stat("Time played (s)", |s| s.time_played.as_secs_f32());
+}
+
 #[macroquad::main("Tetrus")]
 async fn main() {
     let (args, _rest) = opts! {
         synopsis "A Tetris game implemented in Rust.";
         opt autoplay:bool, desc:"Auto-play by AI";
+        opt agent: Option<String>, desc:"AI used to auto-play: heuristic (default), random or tbp";
+        opt bot_command: Option<String>, desc:"Command launching an external bot, for the tbp agent";
         opt n_games: usize=1, desc:"Number of games to play";
         opt speedup: Option<u32>, desc:"Speedup rate of the game";
         opt no_screen: bool, desc:"Do not display the game on screen (for AI testing)";
+        opt lookahead: usize=1, desc:"Number of preview pieces the AI looks ahead";
+        opt beam_width: usize=5, desc:"Number of candidate boards the AI keeps at each step";
+        opt expect_unknown: bool, desc:"Make the AI average over the unknown piece after the preview";
+        opt think_ms: Option<u64>, desc:"Time budget (ms) for each AI decision (default: none)";
+        opt threads: Option<usize>, desc:"Number of threads used by the AI search (default: all cores)";
+        opt high_scores: bool, desc:"Print the high scores and exit";
+        opt versus: bool, desc:"Play a versus match, against the AI if an agent is given";
+        opt attack_table: Option<String>, desc:"JSON file with the lines of garbage sent in versus";
+        opt mode: Option<String>, desc:"Start a game of this mode right away, without the menu: marathon, sprint, ultra, dig or classic";
+        opt start_level: i32=0, desc:"Level the games start from (0 to 19)";
+        opt gravity: String=String::from("guideline"), desc:"Gravity curve: guideline, nes or tgm";
+        opt entry_delay: u64=0, desc:"Delay (ms) before each new piece appears, on top of the line clear animation";
+        opt host: Option<u16>, desc:"Host an online versus match on this port";
+        opt join: Option<String>, desc:"Join the online versus match hosted at this address (eg 127.0.0.1:7878)";
     }
     .parse_or_exit();
 
+    if args.high_scores {
+        HighScoreTable::load().print();
+        return;
+    }
+
     println!("Width: {}, Height: {}", screen_width(), screen_height());
 
-    let mut scores: Vec<Score> = Vec::new();
-    let speedup = args.speedup.unwrap_or(if args.autoplay { 10 } else { 1 });
+    if let Some(n_threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(n_threads)
+            .build_global()
+            .unwrap();
+    }
 
-    for i in 0..args.n_games {
-        println!("Game {}/{}", i + 1, args.n_games);
-        let mut game = Game::new(args.autoplay, speedup, args.no_screen);
-        game.play().await;
-        scores.push(game.score);
+    let agent_name = args
+        .agent
+        .or(args.autoplay.then(|| "heuristic".to_string()));
+
+    let speedup = args
+        .speedup
+        .unwrap_or(if agent_name.is_some() { 10 } else { 1 });
+    let mut agent_config = AgentConfig {
+        search_config: SearchConfig {
+            lookahead: args.lookahead,
+            beam_width: args.beam_width,
+            expect_unknown_piece: args.expect_unknown,
+            time_budget: args.think_ms.map(Duration::from_millis),
+        },
+        bot_command: args.bot_command,
+    };
+
+    let gravity_curve = gravity_curve_from_name(&args.gravity).unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    });
+    if let Err(err) = check_start_level(args.start_level) {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
     }
+    let entry_delay = Duration::from_millis(args.entry_delay);
 
-    println!("Summary stats over {} games:", args.n_games);
-    let n_points = scores.iter().map(|s| s.points).collect();
-    let n_lines_cleared = scores.iter().map(|s| s.total_lines_cleared).collect();
-    let level = scores.iter().map(|s| s.level).collect();
-    println!("Score: {}", stats::summarize(&n_points));
-    println!(
-        "Number of lines cleared: {}",
-        stats::summarize(&n_lines_cleared)
-    );
-    println!("Average level attained: {}", stats::summarize(&level));
+    let attack_table =
+        AttackTable::load_or_default(args.attack_table.as_deref()).unwrap_or_else(|err| {
+            eprintln!("Error: could not read the attack table {}", err);
+            std::process::exit(1);
+        });
+
+    // the command line flags are shortcuts into a game, without going through the menu
+    let net_role = match (args.host, args.join) {
+        (Some(port), None) => Some(NetRole::Host { port }),
+        (None, Some(address)) => Some(NetRole::Join { address }),
+        (None, None) => None,
+        (Some(_), Some(_)) => {
+            eprintln!("Error: --host and --join can't be used together");
+            std::process::exit(1);
+        }
+    };
+    if let Some(net_role) = net_role {
+        if let Err(err) = play_online(&net_role, &attack_table).await {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.versus {
+        let agent = agent_name.as_deref().map(|name| (name, &agent_config));
+        if let Err(err) = play_versus(agent, &attack_table).await {
+            eprintln!("Error: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let skip_menu =
+        args.mode.is_some() || agent_name.is_some() || args.no_screen || args.n_games > 1;
+    if skip_menu {
+        let agent = agent_name.as_deref().map(|name| (name, &agent_config));
+        let mode_name = args.mode.as_deref().unwrap_or("marathon");
+        let result = play_games(
+            mode_name,
+            args.n_games,
+            agent,
+            &GameOptions {
+                start_level: args.start_level,
+                gravity_curve,
+                entry_delay,
+            },
+            speedup,
+            args.no_screen,
+        )
+        .await;
+        match result {
+            Ok((scores, game_stats)) => print_summary(&scores, &game_stats),
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let mut menu = Menu::new(Settings {
+        agent_name: "heuristic".to_string(),
+        search_config: agent_config.search_config,
+        speedup: args.speedup.unwrap_or(10),
+        gravity_curve,
+        start_level: args.start_level,
+        has_bot_command: agent_config.bot_command.is_some(),
+    });
+    loop {
+        let choice = menu.run().await;
+        agent_config.search_config = menu.settings.search_config;
+        let bot_agent = Some((menu.settings.agent_name.as_str(), &agent_config));
+        let options = GameOptions {
+            start_level: menu.settings.start_level,
+            gravity_curve: menu.settings.gravity_curve,
+            entry_delay,
+        };
+        let result = match choice {
+            MenuChoice::Play(mode) => play_games(mode, 1, None, &options, speedup, false)
+                .await
+                .map(|_| ()),
+            MenuChoice::WatchBot => play_games(
+                "marathon",
+                1,
+                bot_agent,
+                &options,
+                menu.settings.speedup,
+                false,
+            )
+            .await
+            .map(|_| ()),
+            MenuChoice::Versus { against_bot } => {
+                play_versus(bot_agent.filter(|_| against_bot), &attack_table).await
+            }
+            MenuChoice::Quit => break,
+        };
+        if let Err(err) = result {
+            eprintln!("Error: {}", err);
+        }
+    }
 }
@@ -0,0 +1,348 @@
+// Front-end shown when the game is launched without any shortcut flag:
+// mode selection, bot settings, high scores and controls.
+use macroquad::{
+    prelude::{is_key_pressed, KeyCode, WHITE, YELLOW},
+    text::draw_text,
+    window::next_frame,
+};
+
+use crate::{
+    agents::AGENT_NAMES,
+    ai::SearchConfig,
+    gravity::{gravity_curve_from_name, GravityCurve, GRAVITY_CURVE_NAMES},
+    high_scores::HighScoreTable,
+    modes::MAX_START_LEVEL,
+};
+
+// what the player picked in the menu
+pub enum MenuChoice {
+    // name of the mode
+    Play(&'static str),
+    // the bot plays marathon
+    WatchBot,
+    // two humans, or a human against the bot
+    Versus { against_bot: bool },
+    Quit,
+}
+
+// settings of the "watch bot" mode and of the games, that can be changed from the menu
+pub struct Settings {
+    pub agent_name: String,
+    pub search_config: SearchConfig,
+    pub speedup: u32,
+    // of the single player games
+    pub gravity_curve: GravityCurve,
+    pub start_level: i32,
+    // the tbp agent is only offered when a bot command was given on the command line
+    pub has_bot_command: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Screen {
+    Main,
+    Settings,
+    HighScores,
+    Controls,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum MainItem {
+    Marathon,
+    Sprint,
+    Ultra,
+    Dig,
+    Classic,
+    Versus,
+    VersusBot,
+    WatchBot,
+    Settings,
+    HighScores,
+    Controls,
+    Quit,
+}
+
+const MAIN_ITEMS: [MainItem; 12] = [
+    MainItem::Marathon,
+    MainItem::Sprint,
+    MainItem::Ultra,
+    MainItem::Dig,
+    MainItem::Classic,
+    MainItem::Versus,
+    MainItem::VersusBot,
+    MainItem::WatchBot,
+    MainItem::Settings,
+    MainItem::HighScores,
+    MainItem::Controls,
+    MainItem::Quit,
+];
+
+impl MainItem {
+    fn label(&self) -> &'static str {
+        return match self {
+            MainItem::Marathon => "Marathon",
+            MainItem::Sprint => "Sprint (40 lines)",
+            MainItem::Ultra => "Ultra (3 minutes)",
+            MainItem::Dig => "Dig (10 garbage rows)",
+            MainItem::Classic => "Classic (NES)",
+            MainItem::Versus => "Versus (2 players)",
+            MainItem::VersusBot => "Versus bot",
+            MainItem::WatchBot => "Watch bot",
+            MainItem::Settings => "Settings",
+            MainItem::HighScores => "High scores",
+            MainItem::Controls => "Controls",
+            MainItem::Quit => "Quit",
+        };
+    }
+}
+
+const N_SETTINGS: usize = 6;
+
+const CONTROLS: [(&str, &str); 10] = [
+    ("Left / Right", "Move the piece"),
+    ("Up", "Rotate the piece clockwise"),
+    ("Z", "Rotate it counter-clockwise"),
+    ("Down", "Soft drop"),
+    ("Space", "Hard drop (not in classic)"),
+    ("Q", "Quit the game"),
+    ("W A S D E / Space", "Left player, in versus"),
+    ("Arrows / Shift / Enter", "Right player, in versus"),
+    ("Up / Down / Enter", "Navigate the menu"),
+    ("Escape", "Back to the main menu"),
+];
+
+// (mode, rules, by_bot) of each page of the high scores screen
+const HIGH_SCORE_PAGES: [(&str, &str, bool); 14] = [
+    ("marathon", "standard", false),
+    ("marathon", "standard", true),
+    ("sprint", "standard", false),
+    ("sprint", "standard", true),
+    ("ultra", "standard", false),
+    ("ultra", "standard", true),
+    ("dig", "standard", false),
+    ("dig", "standard", true),
+    // (on the NES gravity curve)
+    ("classic", "nes", false),
+    ("classic", "nes", true),
+    // (from the usual start levels of NES players)
+    ("classic", "nes-start18", false),
+    ("classic", "nes-start18", true),
+    ("classic", "nes-start19", false),
+    ("classic", "nes-start19", true),
+];
+
+pub struct Menu {
+    pub settings: Settings,
+    screen: Screen,
+    // highlighted line of the current screen
+    selected: usize,
+    high_scores: HighScoreTable,
+    high_score_page: usize,
+}
+
+impl Menu {
+    const FONT_SIZE: f32 = 40.;
+    const SMALL_FONT_SIZE: f32 = 30.;
+    const LEFT_PADDING: f32 = 60.;
+    const TOP_OFFSET: f32 = 80.;
+    const MARGIN_BETWEEN_LINES: f32 = 36.;
+    const MAX_SPEEDUP: u32 = 100;
+    const MAX_LOOKAHEAD: usize = 5;
+    const MAX_BEAM_WIDTH: usize = 50;
+
+    pub fn new(settings: Settings) -> Self {
+        return Menu {
+            settings,
+            screen: Screen::Main,
+            selected: 0,
+            high_scores: HighScoreTable::load(),
+            high_score_page: 0,
+        };
+    }
+
+    // shows the menu until the player picks a mode (or quits)
+    pub async fn run(&mut self) -> MenuChoice {
+        // the last game may have changed the high scores
+        self.high_scores = HighScoreTable::load();
+        loop {
+            if let Some(choice) = self.update() {
+                return choice;
+            }
+            self.draw();
+            next_frame().await;
+        }
+    }
+
+    fn go_to(&mut self, screen: Screen) {
+        self.screen = screen;
+        self.selected = 0;
+    }
+
+    fn move_selection(&mut self, n_lines: usize) {
+        if is_key_pressed(KeyCode::Down) {
+            self.selected = (self.selected + 1) % n_lines;
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.selected = (self.selected + n_lines - 1) % n_lines;
+        }
+    }
+
+    fn update(&mut self) -> Option<MenuChoice> {
+        if self.screen != Screen::Main && is_key_pressed(KeyCode::Escape) {
+            self.go_to(Screen::Main);
+            return None;
+        }
+        match self.screen {
+            Screen::Main => {
+                self.move_selection(MAIN_ITEMS.len());
+                let item = MAIN_ITEMS[self.selected];
+                if !is_key_pressed(KeyCode::Enter) {
+                    return None;
+                }
+                match item {
+                    MainItem::Marathon => return Some(MenuChoice::Play("marathon")),
+                    MainItem::Sprint => return Some(MenuChoice::Play("sprint")),
+                    MainItem::Ultra => return Some(MenuChoice::Play("ultra")),
+                    MainItem::Dig => return Some(MenuChoice::Play("dig")),
+                    MainItem::Classic => return Some(MenuChoice::Play("classic")),
+                    MainItem::Versus => return Some(MenuChoice::Versus { against_bot: false }),
+                    MainItem::VersusBot => return Some(MenuChoice::Versus { against_bot: true }),
+                    MainItem::WatchBot => return Some(MenuChoice::WatchBot),
+                    MainItem::Quit => return Some(MenuChoice::Quit),
+                    MainItem::Settings => self.go_to(Screen::Settings),
+                    MainItem::HighScores => self.go_to(Screen::HighScores),
+                    MainItem::Controls => self.go_to(Screen::Controls),
+                }
+            }
+            Screen::Settings => {
+                self.move_selection(N_SETTINGS);
+                if is_key_pressed(KeyCode::Right) {
+                    self.change_setting(1);
+                }
+                if is_key_pressed(KeyCode::Left) {
+                    self.change_setting(-1);
+                }
+            }
+            Screen::HighScores => {
+                let n_pages = HIGH_SCORE_PAGES.len();
+                if is_key_pressed(KeyCode::Right) {
+                    self.high_score_page = (self.high_score_page + 1) % n_pages;
+                }
+                if is_key_pressed(KeyCode::Left) {
+                    self.high_score_page = (self.high_score_page + n_pages - 1) % n_pages;
+                }
+            }
+            Screen::Controls => (),
+        }
+        return None;
+    }
+
+    fn available_agents(&self) -> Vec<&'static str> {
+        return AGENT_NAMES
+            .iter()
+            .copied()
+            .filter(|&name| name != "tbp" || self.settings.has_bot_command)
+            .collect();
+    }
+
+    fn change_setting(&mut self, step: i32) {
+        let agents = self.available_agents();
+        let settings = &mut self.settings;
+        let add = |value: usize, max: usize| (value as i32 + step).clamp(1, max as i32) as usize;
+        match self.selected {
+            0 => {
+                let index = agents
+                    .iter()
+                    .position(|&name| name == settings.agent_name)
+                    .unwrap_or(0);
+                let new_index = (index as i32 + step).rem_euclid(agents.len() as i32) as usize;
+                settings.agent_name = agents[new_index].to_string();
+            }
+            1 => {
+                // a lookahead of 0 makes the bot greedy
+                let lookahead = settings.search_config.lookahead as i32 + step;
+                settings.search_config.lookahead =
+                    lookahead.clamp(0, Self::MAX_LOOKAHEAD as i32) as usize;
+            }
+            2 => {
+                settings.search_config.beam_width =
+                    add(settings.search_config.beam_width, Self::MAX_BEAM_WIDTH)
+            }
+            3 => {
+                settings.speedup = add(settings.speedup as usize, Self::MAX_SPEEDUP as usize) as u32
+            }
+            4 => {
+                let n_curves = GRAVITY_CURVE_NAMES.len() as i32;
+                let index = GRAVITY_CURVE_NAMES
+                    .iter()
+                    .position(|&name| name == settings.gravity_curve.name())
+                    .unwrap_or(0);
+                let new_index = (index as i32 + step).rem_euclid(n_curves) as usize;
+                settings.gravity_curve =
+                    gravity_curve_from_name(GRAVITY_CURVE_NAMES[new_index]).unwrap();
+            }
+            _ => settings.start_level = (settings.start_level + step).clamp(0, MAX_START_LEVEL),
+        }
+    }
+
+    fn draw_line(&self, text: &str, line: usize, font_size: f32, is_selected: bool) {
+        draw_text(
+            text,
+            Self::LEFT_PADDING,
+            Self::TOP_OFFSET + line as f32 * Self::MARGIN_BETWEEN_LINES,
+            font_size,
+            if is_selected { YELLOW } else { WHITE },
+        );
+    }
+
+    fn draw(&self) {
+        match self.screen {
+            Screen::Main => {
+                self.draw_line("TETRUS", 0, 60., false);
+                for (i, item) in MAIN_ITEMS.iter().enumerate() {
+                    self.draw_line(item.label(), i + 2, Self::FONT_SIZE, i == self.selected);
+                }
+            }
+            Screen::Settings => {
+                self.draw_line("Settings", 0, 60., false);
+                let settings = &self.settings;
+                let lines = [
+                    format!("Bot: < {} >", settings.agent_name),
+                    format!("Bot lookahead: < {} >", settings.search_config.lookahead),
+                    format!("Bot beam width: < {} >", settings.search_config.beam_width),
+                    format!("Bot speed: < x{} >", settings.speedup),
+                    format!("Gravity: < {} >", settings.gravity_curve.name()),
+                    format!("Start level: < {} >", settings.start_level),
+                ];
+                for (i, line) in lines.iter().enumerate() {
+                    self.draw_line(line, i + 2, Self::FONT_SIZE, i == self.selected);
+                }
+                self.draw_line(
+                    "Left / Right to change, Escape to go back",
+                    lines.len() + 3,
+                    Self::SMALL_FONT_SIZE,
+                    false,
+                );
+            }
+            Screen::HighScores => {
+                let (mode, rules, by_bot) = HIGH_SCORE_PAGES[self.high_score_page];
+                self.high_scores
+                    .draw(mode, rules, by_bot, Self::LEFT_PADDING, Self::TOP_OFFSET);
+                self.draw_line(
+                    "Left / Right for more, Escape to go back",
+                    11,
+                    Self::SMALL_FONT_SIZE,
+                    false,
+                );
+            }
+            Screen::Controls => {
+                self.draw_line("Controls", 0, 60., false);
+                for (i, (keys, action)) in CONTROLS.iter().enumerate() {
+                    let y = Self::TOP_OFFSET + (i + 2) as f32 * Self::MARGIN_BETWEEN_LINES;
+                    let font_size = Self::SMALL_FONT_SIZE;
+                    draw_text(keys, Self::LEFT_PADDING, y, font_size, YELLOW);
+                    draw_text(action, Self::LEFT_PADDING + 340., y, font_size, WHITE);
+                }
+            }
+        }
+    }
+}
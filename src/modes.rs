@@ -0,0 +1,294 @@
+// The rules that differ from one game mode to another: goals, level progression,
+// gravity and end conditions. Each mode is defined in one place, by implementing `GameMode`.
+use std::time::Duration;
+
+use crate::{
+    constants::PREVIEW_SIZE,
+    engine::Engine,
+    gravity::{GravityCurve, LOCK_DELAY},
+    score::Score,
+    shapes::{Randomizer, RotationSystem},
+};
+
+// why a game ended
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameEnd {
+    ToppedOut,
+    GoalReached,
+    TimeUp,
+}
+
+// (Send + Sync, so that an engine can be held by the Python environment)
+pub trait GameMode: Send + Sync {
+    // as used in the high scores and on the command line
+    fn name(&self) -> &'static str;
+
+    // level reached after clearing that many lines in total, like on the NES:
+    // the first level-up comes after `first_level_up_lines`, and the next ones every 10 lines
+    fn level(&self, start_level: i32, total_lines_cleared: i32) -> i32 {
+        let first_level_up_lines = first_level_up_lines(start_level);
+        if total_lines_cleared < first_level_up_lines {
+            return start_level;
+        }
+        return start_level + 1 + (total_lines_cleared - first_level_up_lines) / 10;
+    }
+
+    // gravity curve of the game, given the one picked by the player
+    fn gravity_curve(&self, picked: GravityCurve) -> GravityCurve {
+        return picked;
+    }
+
+    // rows per frame the pieces fall by, on the gravity curve of the game
+    fn gravity(&self, curve: GravityCurve, score: &Score) -> f64 {
+        return curve.rows_per_frame(score.level);
+    }
+
+    // number of upcoming pieces shown
+    fn preview_size(&self) -> usize {
+        return PREVIEW_SIZE;
+    }
+
+    fn randomizer(&self) -> Randomizer {
+        return Randomizer::Uniform;
+    }
+
+    fn rotation_system(&self) -> RotationSystem {
+        return RotationSystem::Standard;
+    }
+
+    fn has_hard_drop(&self) -> bool {
+        return true;
+    }
+
+    // frames a piece can rest on the stack before it locks
+    fn lock_delay(&self) -> f64 {
+        return LOCK_DELAY;
+    }
+
+    // number of lines to clear to win, for the modes that have a goal
+    fn line_goal(&self) -> Option<i32> {
+        return None;
+    }
+
+    fn is_goal_reached(&self, engine: &Engine) -> bool {
+        return self
+            .line_goal()
+            .is_some_and(|goal| engine.score.total_lines_cleared >= goal);
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        return None;
+    }
+
+    // rows of garbage the grid starts with
+    fn initial_garbage_rows(&self) -> usize {
+        return 0;
+    }
+
+    // whether the game is over, given the state of the game and the time played so far
+    fn end_of_game(&self, engine: &Engine, time_played: Duration) -> Option<GameEnd> {
+        if self.is_goal_reached(engine) {
+            return Some(GameEnd::GoalReached);
+        }
+        if engine.is_game_over() {
+            return Some(GameEnd::ToppedOut);
+        }
+        if self.time_limit().is_some_and(|limit| time_played >= limit) {
+            return Some(GameEnd::TimeUp);
+        }
+        return None;
+    }
+
+    // runs are ranked by points otherwise
+    fn is_ranked_by_time(&self) -> bool {
+        return false;
+    }
+
+    // only finished runs make it into the high scores of the modes ranked by time
+    fn counts_for_high_scores(&self, end: GameEnd) -> bool {
+        return !self.is_ranked_by_time() || end == GameEnd::GoalReached;
+    }
+
+    // number of lines between two split times, for the modes that have some
+    fn split_lines(&self) -> Option<i32> {
+        return None;
+    }
+}
+
+pub const MODE_NAMES: [&str; 5] = ["marathon", "sprint", "ultra", "dig", "classic"];
+
+pub fn mode_from_name(name: &str) -> Result<Box<dyn GameMode>, String> {
+    return match name {
+        "marathon" => Ok(Box::new(Marathon)),
+        "sprint" => Ok(Box::new(Sprint)),
+        "ultra" => Ok(Box::new(Ultra)),
+        "dig" => Ok(Box::new(Dig)),
+        "classic" => Ok(Box::new(Classic)),
+        _ => Err(format!(
+            "unknown mode '{}', expected one of {:?}",
+            name, MODE_NAMES
+        )),
+    };
+}
+
+// the highest level that can be picked on the NES
+pub const MAX_START_LEVEL: i32 = 19;
+
+pub fn check_start_level(start_level: i32) -> Result<(), String> {
+    if !(0..=MAX_START_LEVEL).contains(&start_level) {
+        return Err(format!(
+            "the start level must be between 0 and {}",
+            MAX_START_LEVEL
+        ));
+    }
+    return Ok(());
+}
+
+// The transition: lines to clear before leaving the start level.
+// From level 0 it is 10 lines, as for every next level-up; from higher levels
+// the start level lasts longer, eg 100 lines from level 9 and 130 from level 18
+pub fn first_level_up_lines(start_level: i32) -> i32 {
+    return i32::min(start_level * 10 + 10, i32::max(100, start_level * 10 - 50));
+}
+
+// Endless, until the stack tops out, ranked by points
+pub struct Marathon;
+
+impl GameMode for Marathon {
+    fn name(&self) -> &'static str {
+        return "marathon";
+    }
+}
+
+// Clear 40 lines as fast as possible, ranked by time
+pub struct Sprint;
+
+impl Sprint {
+    const LINES: i32 = 40;
+    const SPLIT_LINES: i32 = 10;
+}
+
+impl GameMode for Sprint {
+    fn name(&self) -> &'static str {
+        return "sprint";
+    }
+
+    fn line_goal(&self) -> Option<i32> {
+        return Some(Self::LINES);
+    }
+
+    fn is_ranked_by_time(&self) -> bool {
+        return true;
+    }
+
+    fn split_lines(&self) -> Option<i32> {
+        return Some(Self::SPLIT_LINES);
+    }
+}
+
+// Score as many points as possible in 3 minutes, at a fixed speed
+pub struct Ultra;
+
+impl Ultra {
+    const TIME_LIMIT: Duration = Duration::from_secs(180);
+    // level whose gravity is used for the whole game
+    const GRAVITY_LEVEL: i32 = 0;
+}
+
+impl GameMode for Ultra {
+    fn name(&self) -> &'static str {
+        return "ultra";
+    }
+
+    fn gravity(&self, curve: GravityCurve, _score: &Score) -> f64 {
+        return curve.rows_per_frame(Self::GRAVITY_LEVEL);
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        return Some(Self::TIME_LIMIT);
+    }
+}
+
+// Dig through rows of garbage as fast as possible, ranked by time
+pub struct Dig;
+
+impl Dig {
+    const GARBAGE_ROWS: usize = 10;
+}
+
+impl GameMode for Dig {
+    fn name(&self) -> &'static str {
+        return "dig";
+    }
+
+    // every garbage row got cleared
+    fn is_goal_reached(&self, engine: &Engine) -> bool {
+        return engine.grid().count_garbage_rows() == 0;
+    }
+
+    fn initial_garbage_rows(&self) -> usize {
+        return Self::GARBAGE_ROWS;
+    }
+
+    fn is_ranked_by_time(&self) -> bool {
+        return true;
+    }
+}
+
+// NES Tetris: its gravity, randomizer and rotation, a single preview piece
+// and no hard drop, until the stack tops out
+pub struct Classic;
+
+impl GameMode for Classic {
+    fn name(&self) -> &'static str {
+        return "classic";
+    }
+
+    fn gravity_curve(&self, _picked: GravityCurve) -> GravityCurve {
+        return GravityCurve::Nes;
+    }
+
+    fn preview_size(&self) -> usize {
+        return 1;
+    }
+
+    fn randomizer(&self) -> Randomizer {
+        return Randomizer::Nes;
+    }
+
+    fn rotation_system(&self) -> RotationSystem {
+        return RotationSystem::Nes;
+    }
+
+    fn has_hard_drop(&self) -> bool {
+        return false;
+    }
+
+    // a piece locks as soon as the next row is due
+    fn lock_delay(&self) -> f64 {
+        return 0.;
+    }
+}
+
+// eg "1:02.345"
+pub fn format_time(time: Duration) -> String {
+    let millis = time.as_millis();
+    return format!(
+        "{}:{:02}.{:03}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis % 1000
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_level_up_follows_the_nes_transition() {
+        for (start_level, lines) in [(0, 10), (9, 100), (15, 100), (18, 130), (19, 140)] {
+            assert_eq!(first_level_up_lines(start_level), lines);
+        }
+    }
+}
@@ -1,14 +1,17 @@
 use macroquad::prelude::{is_key_released, KeyCode};
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum SimpleMove {
     Left,
     Right,
     Down,
+    // clockwise
     Rotate,
+    RotateCounterClockwise,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Move {
     Simple(SimpleMove),
     // hard-drop means we drop the piece immediately as low as it can go
@@ -17,17 +20,54 @@ pub enum Move {
     HardDrop,
 }
 
-impl Move {
-    pub fn from_key_press() -> Option<Move> {
-        if is_key_released(KeyCode::Left) {
+// keys that play each move, for one player
+#[derive(Clone, Copy, Debug)]
+pub struct KeyBindings {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub down: KeyCode,
+    pub rotate: KeyCode,
+    pub rotate_counter_clockwise: KeyCode,
+    pub hard_drop: KeyCode,
+}
+
+impl KeyBindings {
+    pub const ARROWS_SPACE: KeyBindings = KeyBindings {
+        left: KeyCode::Left,
+        right: KeyCode::Right,
+        down: KeyCode::Down,
+        rotate: KeyCode::Up,
+        rotate_counter_clockwise: KeyCode::Z,
+        hard_drop: KeyCode::Space,
+    };
+    // for the left player, when two humans share the keyboard
+    pub const WASD_SPACE: KeyBindings = KeyBindings {
+        left: KeyCode::A,
+        right: KeyCode::D,
+        down: KeyCode::S,
+        rotate: KeyCode::W,
+        rotate_counter_clockwise: KeyCode::E,
+        hard_drop: KeyCode::Space,
+    };
+    // for the right player, when two humans share the keyboard
+    pub const ARROWS_ENTER: KeyBindings = KeyBindings {
+        rotate_counter_clockwise: KeyCode::RightShift,
+        hard_drop: KeyCode::Enter,
+        ..Self::ARROWS_SPACE
+    };
+
+    pub fn move_pressed(&self) -> Option<Move> {
+        if is_key_released(self.left) {
             return Some(Move::Simple(SimpleMove::Left));
-        } else if is_key_released(KeyCode::Right) {
+        } else if is_key_released(self.right) {
             return Some(Move::Simple(SimpleMove::Right));
-        } else if is_key_released(KeyCode::Up) {
+        } else if is_key_released(self.rotate) {
             return Some(Move::Simple(SimpleMove::Rotate));
-        } else if is_key_released(KeyCode::Down) {
+        } else if is_key_released(self.rotate_counter_clockwise) {
+            return Some(Move::Simple(SimpleMove::RotateCounterClockwise));
+        } else if is_key_released(self.down) {
             return Some(Move::Simple(SimpleMove::Down));
-        } else if is_key_released(KeyCode::Space) {
+        } else if is_key_released(self.hard_drop) {
             return Some(Move::HardDrop);
         }
         return None;
@@ -46,3 +46,12 @@ impl MusicPlayer {
         );
     }
 }
+
+impl Drop for MusicPlayer {
+    // so that the music of a game stops when going back to the menu
+    fn drop(&mut self) {
+        if let Some(music) = self.main_music {
+            stop_sound(music);
+        }
+    }
+}
@@ -0,0 +1,651 @@
+// Versus over the network. Both instances simulate the two games in lockstep, from the same seed
+// and the inputs of both players, so that only the inputs need to go through the connection.
+// After each of its pieces, a player also sends a hash of its game and the garbage it sent,
+// that the other instance checks against its own copy of the game to detect desyncs.
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use macroquad::{
+    camera::{set_camera, set_default_camera},
+    prelude::{is_key_down, KeyCode, WHITE},
+    text::draw_text,
+    window::{next_frame, request_new_screen_size},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    engine::Engine,
+    gravity::FRAMES_PER_SECOND,
+    moves::{KeyBindings, Move},
+    music::MusicPlayer,
+    versus::{
+        draw_centered, field_camera, AttackTable, VersusMode, VersusState, FIELD_HEIGHT,
+        FIELD_WIDTH, NAME_POSITION,
+    },
+};
+
+// both instances must simulate the games the same way
+const PROTOCOL_VERSION: u32 = 1;
+// ticks between a key press and its effect, that hide the latency of the network
+const INPUT_DELAY: u64 = 4;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    // from the host, once a player joined
+    Start {
+        version: u32,
+        seed: u64,
+        attack_table: AttackTable,
+    },
+    // move of the sender at the given tick, if any
+    Input {
+        tick: u64,
+        #[serde(rename = "move")]
+        move_: Option<Move>,
+    },
+    // state of the game of the sender after its `index`-th piece (from 0)
+    Piece {
+        index: usize,
+        hash: u64,
+        lines_sent: u32,
+    },
+    Quit,
+}
+
+// how a match ended, for the local player
+enum MatchEnd {
+    // the last one standing, or None on a draw
+    Over(Option<usize>),
+    LocalQuit,
+    OpponentQuit,
+}
+
+// what a player's game looked like after one of its pieces got locked
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PieceRecord {
+    pub hash: u64,
+    pub lines_sent: u32,
+}
+
+// A versus match that advances one tick (a frame) at a time, given the inputs of both players at that tick.
+// It does not depend on the clock, so that it plays out the same way on both instances.
+pub struct LockstepMatch {
+    engines: [Engine; 2],
+    versus: [VersusState; 2],
+    // pieces of each player, in the order they got locked
+    pieces: [Vec<PieceRecord>; 2],
+}
+
+impl LockstepMatch {
+    pub fn new(seed: u64, attack_table: &AttackTable) -> Self {
+        return LockstepMatch {
+            engines: [0, 1].map(|_| Engine::with_mode(seed, Box::new(VersusMode))),
+            versus: [0, 1].map(|_| VersusState::new(attack_table.clone())),
+            pieces: [Vec::new(), Vec::new()],
+        };
+    }
+
+    pub fn engine(&self, player: usize) -> &Engine {
+        return &self.engines[player];
+    }
+
+    pub fn versus_state(&self, player: usize) -> &VersusState {
+        return &self.versus[player];
+    }
+
+    pub fn pieces(&self, player: usize) -> &[PieceRecord] {
+        return &self.pieces[player];
+    }
+
+    pub fn is_over(&self) -> bool {
+        return self.engines.iter().any(|engine| engine.is_game_over());
+    }
+
+    // the last one standing, once the match is over (None on a draw)
+    pub fn winner(&self) -> Option<usize> {
+        return match (
+            self.engines[0].is_game_over(),
+            self.engines[1].is_game_over(),
+        ) {
+            (true, false) => Some(1),
+            (false, true) => Some(0),
+            _ => None,
+        };
+    }
+
+    pub fn step(&mut self, inputs: [Option<Move>; 2]) {
+        let mut is_locked = [false; 2];
+        for ((engine, input), is_locked) in self.engines.iter_mut().zip(inputs).zip(&mut is_locked)
+        {
+            if engine.is_game_over() {
+                continue;
+            }
+            *is_locked = match input {
+                Some(move_) => engine.apply_move(&move_),
+                None => false,
+            };
+            if !*is_locked {
+                *is_locked = engine.apply_gravity(1.);
+            }
+        }
+        // (the garbage only gets into a grid when its player locks a piece,
+        // so the order of the players does not matter)
+        for player in [0, 1] {
+            if is_locked[player] {
+                self.on_piece_locked(player);
+            }
+        }
+    }
+
+    fn on_piece_locked(&mut self, player: usize) {
+        if let Some(lock) = self.engines[player].last_lock() {
+            self.versus[player].on_piece_locked(&lock, &mut self.engines[player]);
+        }
+        let lines_sent = self.versus[player].take_outgoing();
+        self.versus[1 - player].receive(lines_sent);
+        self.pieces[player].push(PieceRecord {
+            hash: self.engines[player].state_hash(),
+            lines_sent,
+        });
+    }
+}
+
+// One JSON message per line, read on a separate thread so that the game never waits for the network
+struct Connection {
+    stream: TcpStream,
+    received: Receiver<Message>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> Result<Self, String> {
+        let to_string = |err: io::Error| err.to_string();
+        stream.set_nonblocking(false).map_err(to_string)?;
+        stream.set_nodelay(true).map_err(to_string)?;
+        let reader = BufReader::new(stream.try_clone().map_err(to_string)?);
+        let (sender, received) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                match serde_json::from_str(&line) {
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => eprintln!("Ignoring invalid message '{}': {}", line, err),
+                }
+            }
+        });
+        return Ok(Connection { stream, received });
+    }
+
+    fn send(&mut self, message: &Message) -> Result<(), String> {
+        let mut line = serde_json::to_string(message).map_err(|err| err.to_string())?;
+        line.push('\n');
+        return self
+            .stream
+            .write_all(line.as_bytes())
+            .map_err(|err| format!("connection lost: {}", err));
+    }
+
+    // None if no message arrived yet, an error once the opponent closed the connection
+    fn try_receive(&self) -> Result<Option<Message>, String> {
+        return match self.received.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err("connection closed by the opponent".to_string()),
+        };
+    }
+}
+
+impl Drop for Connection {
+    // the reading thread holds a copy of the stream, that would keep the connection open
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+// the host listens on a port, and the other player joins with the address of the host
+pub enum NetRole {
+    Host { port: u16 },
+    Join { address: String },
+}
+
+// waits for an opponent, showing a message until one joins or Q gets pressed
+async fn accept_opponent(port: u16) -> Result<Option<TcpStream>, String> {
+    let to_string = |err: io::Error| format!("port {}: {}", port, err);
+    let listener = TcpListener::bind(("0.0.0.0", port)).map_err(to_string)?;
+    listener.set_nonblocking(true).map_err(to_string)?;
+    loop {
+        match listener.accept() {
+            Ok((stream, address)) => {
+                println!("{} joined", address);
+                return Ok(Some(stream));
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => (),
+            Err(err) => return Err(to_string(err)),
+        }
+        draw_centered(&format!("Waiting on port {}...", port));
+        next_frame().await;
+        if is_key_down(KeyCode::Q) {
+            return Ok(None);
+        }
+    }
+}
+
+// Sets up a match with another instance: the host picks the seed and the attack table.
+// Returns None if the host gave up waiting for an opponent
+async fn connect(
+    role: &NetRole,
+    attack_table: &AttackTable,
+) -> Result<Option<(Connection, u64, AttackTable, usize)>, String> {
+    match role {
+        NetRole::Host { port } => {
+            let Some(stream) = accept_opponent(*port).await? else {
+                return Ok(None);
+            };
+            let mut connection = Connection::new(stream)?;
+            let seed = rand::random();
+            connection.send(&Message::Start {
+                version: PROTOCOL_VERSION,
+                seed,
+                attack_table: attack_table.clone(),
+            })?;
+            return Ok(Some((connection, seed, attack_table.clone(), 0)));
+        }
+        NetRole::Join { address } => {
+            return Ok(join_host(address)
+                .await?
+                .map(|(connection, seed, attack_table)| (connection, seed, attack_table, 1)));
+        }
+    }
+}
+
+// joins the host, showing a message until the host starts the match or Q gets pressed
+async fn join_host(address: &str) -> Result<Option<(Connection, u64, AttackTable)>, String> {
+    // connecting blocks, so it happens on another thread for the window to stay responsive
+    let (sender, connected) = mpsc::channel();
+    let host_address = address.to_string();
+    thread::spawn(move || {
+        // (the receiving end is gone if the player gave up)
+        let _ = sender.send(TcpStream::connect(host_address));
+    });
+    let start_time = Instant::now();
+    let mut connection: Option<Connection> = None;
+    loop {
+        match &connection {
+            None => {
+                if let Ok(stream) = connected.try_recv() {
+                    let stream = stream.map_err(|err| format!("{}: {}", address, err))?;
+                    connection = Some(Connection::new(stream)?);
+                }
+            }
+            Some(established) => {
+                let message = established
+                    .try_receive()
+                    .map_err(|_| "connection closed by the host".to_string())?;
+                match message {
+                    Some(Message::Start {
+                        version,
+                        seed,
+                        attack_table,
+                    }) => {
+                        if version != PROTOCOL_VERSION {
+                            return Err(format!(
+                                "the host uses version {} of the protocol, and we use version {}",
+                                version, PROTOCOL_VERSION
+                            ));
+                        }
+                        return Ok(connection.map(|connection| (connection, seed, attack_table)));
+                    }
+                    Some(message) => {
+                        return Err(format!("unexpected message from the host: {:?}", message));
+                    }
+                    None => (),
+                }
+            }
+        }
+        if start_time.elapsed() > HANDSHAKE_TIMEOUT {
+            return Err(match connection {
+                None => format!("{}: timed out", address),
+                Some(_) => "the host did not start the match".to_string(),
+            });
+        }
+        draw_centered(&format!("Joining {}...", address));
+        next_frame().await;
+        if is_key_down(KeyCode::Q) {
+            return Ok(None);
+        }
+    }
+}
+
+fn draw_field(lockstep: &LockstepMatch, player: usize, position: usize, name: &str) {
+    set_camera(&field_camera(position));
+    let engine = lockstep.engine(player);
+    engine.grid().draw();
+    engine.score.draw();
+    engine.next_shapes().draw();
+    lockstep.versus_state(player).draw_meter();
+    draw_text(name, NAME_POSITION.0, NAME_POSITION.1, 40., WHITE);
+    set_default_camera();
+}
+
+// Compares the pieces the opponent told us about with the ones we simulated, and
+// drops them from `remote_pieces` (the ones we did not simulate yet are kept)
+fn check_remote_pieces(
+    lockstep: &LockstepMatch,
+    remote_player: usize,
+    remote_pieces: &mut VecDeque<(usize, PieceRecord)>,
+) -> Result<(), String> {
+    while let Some(&(index, remote_piece)) = remote_pieces.front() {
+        let Some(local_piece) = lockstep.pieces(remote_player).get(index) else {
+            break;
+        };
+        if *local_piece != remote_piece {
+            return Err(format!(
+                "desync at piece {} of the opponent: {:?} here, {:?} there",
+                index + 1,
+                local_piece,
+                remote_piece
+            ));
+        }
+        remote_pieces.pop_front();
+    }
+    return Ok(());
+}
+
+// Plays the match until it is over, with the local player on the left
+async fn run_match(
+    connection: &mut Connection,
+    lockstep: &mut LockstepMatch,
+    local_player: usize,
+) -> Result<MatchEnd, String> {
+    let remote_player = 1 - local_player;
+    // moves of each player for the next ticks to simulate.
+    // nobody moves during the first ticks, as the inputs get delayed
+    let mut inputs: [VecDeque<Option<Move>>; 2] =
+        [0, 1].map(|_| (0..INPUT_DELAY).map(|_| None).collect());
+    // next tick to simulate
+    let mut tick: u64 = 0;
+    // next tick for which the local player has no input yet
+    let mut next_local_tick = INPUT_DELAY;
+    let mut next_remote_tick = INPUT_DELAY;
+    // key pressed and not yet sent
+    let mut pressed_move: Option<Move> = None;
+    // pieces of the opponent, as told by the opponent, that we did not simulate yet
+    let mut remote_pieces: VecDeque<(usize, PieceRecord)> = VecDeque::new();
+    let mut is_disconnected = false;
+    let start_time = Instant::now();
+
+    while !lockstep.is_over() {
+        pressed_move = pressed_move.or(KeyBindings::ARROWS_SPACE.move_pressed());
+        if is_key_down(KeyCode::Q) {
+            // the connection may already be closed
+            let _ = connection.send(&Message::Quit);
+            return Ok(MatchEnd::LocalQuit);
+        }
+
+        // the local player does not get further ahead than the input delay
+        let clock_tick = (start_time.elapsed().as_secs_f64() * FRAMES_PER_SECOND) as u64;
+        while next_local_tick <= clock_tick.min(tick) + INPUT_DELAY {
+            let move_ = pressed_move.take();
+            connection.send(&Message::Input {
+                tick: next_local_tick,
+                move_,
+            })?;
+            inputs[local_player].push_back(move_);
+            next_local_tick += 1;
+        }
+
+        while !is_disconnected {
+            match connection.try_receive() {
+                Ok(Some(Message::Input { tick, move_ })) => {
+                    if tick != next_remote_tick {
+                        return Err(format!(
+                            "got the input of tick {} instead of {}",
+                            tick, next_remote_tick
+                        ));
+                    }
+                    inputs[remote_player].push_back(move_);
+                    next_remote_tick += 1;
+                }
+                Ok(Some(Message::Piece {
+                    index,
+                    hash,
+                    lines_sent,
+                })) => remote_pieces.push_back((index, PieceRecord { hash, lines_sent })),
+                Ok(Some(Message::Quit)) => return Ok(MatchEnd::OpponentQuit),
+                Ok(Some(message)) => {
+                    return Err(format!("unexpected message: {:?}", message));
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    is_disconnected = true;
+                }
+            }
+        }
+
+        // once disconnected, whatever the opponent sent before still gets played
+        while (tick < clock_tick || is_disconnected) && !lockstep.is_over() {
+            let (Some(&local_move), Some(&remote_move)) =
+                (inputs[local_player].front(), inputs[remote_player].front())
+            else {
+                break;
+            };
+            inputs[0].pop_front();
+            inputs[1].pop_front();
+            let mut moves = [None, None];
+            moves[local_player] = local_move;
+            moves[remote_player] = remote_move;
+
+            let n_local_pieces = lockstep.pieces(local_player).len();
+            lockstep.step(moves);
+            tick += 1;
+            for index in n_local_pieces..lockstep.pieces(local_player).len() {
+                let piece = lockstep.pieces(local_player)[index];
+                connection.send(&Message::Piece {
+                    index,
+                    hash: piece.hash,
+                    lines_sent: piece.lines_sent,
+                })?;
+            }
+        }
+        if is_disconnected && !lockstep.is_over() && inputs[remote_player].is_empty() {
+            return Err("connection lost".to_string());
+        }
+
+        check_remote_pieces(lockstep, remote_player, &mut remote_pieces)?;
+
+        draw_field(lockstep, local_player, 0, "You");
+        draw_field(lockstep, remote_player, 1, "Opponent");
+        next_frame().await;
+    }
+    return Ok(MatchEnd::Over(lockstep.winner()));
+}
+
+// Hosts or joins a match against another instance of the game, and shows the result
+pub async fn play_online(role: &NetRole, attack_table: &AttackTable) -> Result<(), String> {
+    let Some((mut connection, seed, attack_table, local_player)) =
+        connect(role, attack_table).await?
+    else {
+        return Ok(());
+    };
+    let mut lockstep = LockstepMatch::new(seed, &attack_table);
+    let music_player = MusicPlayer::new(true);
+    request_new_screen_size(2. * FIELD_WIDTH, FIELD_HEIGHT);
+
+    let result = run_match(&mut connection, &mut lockstep, local_player).await;
+    let message = match &result {
+        Ok(MatchEnd::Over(Some(winner))) if *winner == local_player => "You win!",
+        Ok(MatchEnd::Over(Some(_))) => "You lose!",
+        Ok(MatchEnd::Over(None)) => "Draw!",
+        Ok(MatchEnd::OpponentQuit) => "The opponent left",
+        Ok(MatchEnd::LocalQuit) => {
+            request_new_screen_size(FIELD_WIDTH, FIELD_HEIGHT);
+            return Ok(());
+        }
+        // (the details get printed by the caller)
+        Err(_) => "Match aborted",
+    };
+    println!("{}", message);
+    music_player.play_game_over();
+    // (the connection stays open meanwhile, for the opponent to finish simulating the match)
+    let start_time = Instant::now();
+    while start_time.elapsed() < Duration::from_secs(3) {
+        draw_field(&lockstep, local_player, 0, "You");
+        draw_field(&lockstep, 1 - local_player, 1, "Opponent");
+        draw_centered(message);
+        next_frame().await;
+    }
+    request_new_screen_size(FIELD_WIDTH, FIELD_HEIGHT);
+    return result.map(|_| ());
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::moves::SimpleMove;
+
+    const N_TICKS: usize = 5000;
+    const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // a key press every few ticks, the same for a given seed
+    fn random_inputs(seed: u64) -> Vec<[Option<Move>; 2]> {
+        let moves = [
+            Move::Simple(SimpleMove::Left),
+            Move::Simple(SimpleMove::Right),
+            Move::Simple(SimpleMove::Rotate),
+            Move::Simple(SimpleMove::RotateCounterClockwise),
+            Move::Simple(SimpleMove::Down),
+            Move::HardDrop,
+        ];
+        let mut rng = StdRng::seed_from_u64(seed);
+        return (0..N_TICKS)
+            .map(|_| [0, 1].map(|_| rng.gen_bool(0.2).then(|| moves[rng.gen_range(0..6)])))
+            .collect();
+    }
+
+    fn receive(connection: &Connection) -> Result<Message, String> {
+        let start_time = Instant::now();
+        while start_time.elapsed() < RECEIVE_TIMEOUT {
+            if let Some(message) = connection.try_receive()? {
+                return Ok(message);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        panic!("no message received");
+    }
+
+    #[test]
+    fn same_inputs_play_out_the_same() {
+        let attack_table = AttackTable::default();
+        let mut matches = [0, 1].map(|_| LockstepMatch::new(42, &attack_table));
+        for inputs in random_inputs(0) {
+            for lockstep in matches.iter_mut() {
+                if !lockstep.is_over() {
+                    lockstep.step(inputs);
+                }
+            }
+        }
+        for player in 0..2 {
+            assert!(!matches[0].pieces(player).is_empty());
+            assert_eq!(matches[0].pieces(player), matches[1].pieces(player));
+        }
+        assert_eq!(matches[0].winner(), matches[1].winner());
+    }
+
+    #[test]
+    fn messages_go_through_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut joiner = Connection::new(TcpStream::connect(address).unwrap()).unwrap();
+        let mut host = Connection::new(listener.accept().unwrap().0).unwrap();
+
+        host.send(&Message::Start {
+            version: PROTOCOL_VERSION,
+            seed: 42,
+            attack_table: AttackTable::default(),
+        })
+        .unwrap();
+        match receive(&joiner).unwrap() {
+            Message::Start { version, seed, .. } => {
+                assert_eq!(version, PROTOCOL_VERSION);
+                assert_eq!(seed, 42);
+            }
+            message => panic!("unexpected message: {:?}", message),
+        }
+        for tick in 0..10 {
+            let move_ = (tick % 3 == 0).then_some(Move::HardDrop);
+            joiner.send(&Message::Input { tick, move_ }).unwrap();
+            match receive(&host).unwrap() {
+                Message::Input {
+                    tick: received_tick,
+                    move_: received_move,
+                } => {
+                    assert_eq!(received_tick, tick);
+                    assert_eq!(received_move, move_);
+                }
+                message => panic!("unexpected message: {:?}", message),
+            }
+        }
+
+        // the host finds out once the joiner left
+        drop(joiner);
+        assert!(receive(&host).is_err());
+    }
+
+    #[test]
+    fn detects_desyncs() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut joiner = Connection::new(TcpStream::connect(address).unwrap()).unwrap();
+        let host = Connection::new(listener.accept().unwrap().0).unwrap();
+
+        let mut lockstep = LockstepMatch::new(42, &AttackTable::default());
+        while lockstep.pieces(1).len() < 2 {
+            lockstep.step([None, Some(Move::HardDrop)]);
+        }
+        let mut remote_pieces = VecDeque::new();
+        for (index, piece) in lockstep.pieces(1).iter().enumerate() {
+            // the joiner got a different second piece
+            let hash = if index == 0 {
+                piece.hash
+            } else {
+                piece.hash ^ 1
+            };
+            joiner
+                .send(&Message::Piece {
+                    index,
+                    hash,
+                    lines_sent: piece.lines_sent,
+                })
+                .unwrap();
+            match receive(&host).unwrap() {
+                Message::Piece {
+                    index,
+                    hash,
+                    lines_sent,
+                } => remote_pieces.push_back((index, PieceRecord { hash, lines_sent })),
+                message => panic!("unexpected message: {:?}", message),
+            }
+        }
+
+        let err = check_remote_pieces(&lockstep, 1, &mut remote_pieces).unwrap_err();
+        assert!(err.starts_with("desync at piece 2"), "{}", err);
+        // the first piece matched
+        assert_eq!(remote_pieces.len(), 1);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::VecDeque;
+
+use crate::{
+    bitboard::BitBoard,
+    constants::{GRID_HEIGHT, GRID_WIDTH},
+    moves::{Move, SimpleMove},
+    shapes::ShapePosition,
+};
+
+pub struct Placement {
+    // where the piece ends up once it is locked
+    pub shape: ShapePosition,
+    // inputs to play from the spawn position, in order (ends with a hard-drop)
+    pub moves: Vec<Move>,
+}
+
+// order in which we try the inputs at each step of the search
+// (this only decides which path wins among paths of equal length)
+const SEARCH_MOVES: [SimpleMove; 5] = [
+    SimpleMove::Rotate,
+    SimpleMove::RotateCounterClockwise,
+    SimpleMove::Left,
+    SimpleMove::Right,
+    SimpleMove::Down,
+];
+
+// Piece origins can sit a few cells outside of the grid (pixel offsets range
+// from -1 to 3 with the standard rotations, and from -2 to 1 with the NES ones),
+// so the table of visited states is padded on every side.
+const PADDING: i16 = 4;
+const N_STATE_ROWS: usize = (GRID_HEIGHT + 2 * PADDING) as usize;
+const N_STATE_COLS: usize = (GRID_WIDTH + 2 * PADDING) as usize;
+const MAX_ROTATIONS: usize = 4;
+const N_STATES: usize = N_STATE_ROWS * N_STATE_COLS * MAX_ROTATIONS;
+// distance between a state and the one right below it in the table
+const ROW_STRIDE: usize = N_STATE_COLS * MAX_ROTATIONS;
+const UNVISITED: u16 = u16::MAX;
+
+fn state_index(shape: &ShapePosition) -> usize {
+    let (row, col) = shape.origin();
+    let row = (row + PADDING) as usize;
+    let col = (col + PADDING) as usize;
+    return (row * N_STATE_COLS + col) * MAX_ROTATIONS + shape.rotation_index;
+}
+
+// a visited state, with the index of the state it was reached from
+struct SearchState {
+    shape: ShapePosition,
+    parent: Option<(usize, SimpleMove)>,
+}
+
+fn path_to(state_index: usize, states: &[SearchState]) -> Vec<Move> {
+    let mut moves = vec![Move::HardDrop];
+    let mut current = state_index;
+    while let Some((parent, move_)) = states[current].parent {
+        moves.push(Move::Simple(move_));
+        current = parent;
+    }
+    moves.reverse();
+    return moves;
+}
+
+// Breadth-first search over every (row, column, rotation) state the piece can
+// reach with the game's movement rules, starting from `start`.
+// `board` must not contain the piece itself.
+// Returns one entry per distinct lock position, along with the shortest input
+// sequence that reaches it: since states are visited by increasing distance,
+// the first state whose hard-drop lands on a given position gives the minimal path.
+// This covers tucks (sliding under an overhang after a soft drop) and spins.
+pub fn enumerate_placements(board: &BitBoard, start: &ShapePosition) -> Vec<Placement> {
+    let mut result = Vec::new();
+    if !board.can_set_pixels(&start.get_pixels()) {
+        return result;
+    }
+
+    // index in `states` of each visited (row, column, rotation) state
+    let mut visited: Vec<u16> = vec![UNVISITED; N_STATES];
+    let mut states = vec![SearchState {
+        shape: *start,
+        parent: None,
+    }];
+    visited[state_index(start)] = 0;
+    let mut queue = VecDeque::from([0]);
+
+    while let Some(current) = queue.pop_front() {
+        let shape = states[current].shape;
+        for move_ in SEARCH_MOVES {
+            let next = shape.moved_to(&move_);
+            // `next` is one step away from a valid state, so its origin is within the padding
+            let next_index = state_index(&next);
+            if visited[next_index] != UNVISITED || !board.can_set_pixels(&next.get_pixels()) {
+                continue;
+            }
+            visited[next_index] = states.len() as u16;
+            states.push(SearchState {
+                shape: next,
+                parent: Some((current, move_)),
+            });
+            queue.push_back(states.len() - 1);
+        }
+    }
+
+    // Where each state lands after a hard-drop. The cell right below a valid state
+    // is either blocked, or a state that was visited too (thanks to the Down move),
+    // so this can be resolved from the bottom of the table up, without re-walking columns.
+    let mut landing: Vec<u16> = vec![UNVISITED; N_STATES];
+    for index in (0..N_STATES).rev() {
+        if visited[index] == UNVISITED {
+            continue;
+        }
+        let below = index + ROW_STRIDE;
+        landing[index] = if below < N_STATES && visited[below] != UNVISITED {
+            landing[below]
+        } else {
+            visited[index]
+        };
+    }
+
+    // each rotation of a piece has a distinct outline, so distinct
+    // landing states always cover distinct cells
+    let mut seen_locks = vec![false; states.len()];
+    for (current, state) in states.iter().enumerate() {
+        let locked = landing[state_index(&state.shape)] as usize;
+        if !seen_locks[locked] {
+            seen_locks[locked] = true;
+            result.push(Placement {
+                shape: states[locked].shape,
+                moves: path_to(current, &states),
+            });
+        }
+    }
+    return result;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRAIGHT_INDEX: usize = 1;
+    const T_INDEX: usize = 2;
+
+    fn board_with(pixels: &[(i16, i16)]) -> BitBoard {
+        let mut board = BitBoard::new();
+        board.set_pixels(pixels);
+        return board;
+    }
+
+    fn find_placement<'a>(
+        placements: &'a [Placement],
+        pixels: &[(i16, i16)],
+    ) -> Option<&'a Placement> {
+        let mut target = pixels.to_vec();
+        target.sort();
+        return placements.iter().find(|placement| {
+            let mut locked = placement.shape.get_pixels().to_vec();
+            locked.sort();
+            return locked == target;
+        });
+    }
+
+    // the last input before the hard drop
+    fn last_move(placement: &Placement) -> Move {
+        return placement.moves[placement.moves.len() - 2];
+    }
+
+    #[test]
+    fn finds_tucks_under_an_overhang() {
+        // a roof over the bottom left corner, that a hard drop can't get under
+        let board = board_with(&[(18, 0), (18, 1), (18, 2), (18, 3)]);
+        let placements = enumerate_placements(&board, &ShapePosition::with_shape(STRAIGHT_INDEX));
+        let tuck = find_placement(&placements, &[(19, 0), (19, 1), (19, 2), (19, 3)])
+            .expect("no tuck under the overhang");
+        assert_eq!(last_move(tuck), Move::Simple(SimpleMove::Left));
+    }
+
+    #[test]
+    fn finds_t_spin_slots() {
+        // a slot for a T pointing down, covered on its left side
+        let mut pixels = vec![(17, 3)];
+        for j in 0..GRID_WIDTH {
+            if j != 4 {
+                pixels.push((19, j));
+            }
+            if !(3..=5).contains(&j) {
+                pixels.push((18, j));
+            }
+        }
+        let board = board_with(&pixels);
+        let placements = enumerate_placements(&board, &ShapePosition::with_shape(T_INDEX));
+        let t_spin = find_placement(&placements, &[(18, 3), (18, 4), (18, 5), (19, 4)])
+            .expect("no T-spin into the slot");
+        assert_eq!(last_move(t_spin), Move::Simple(SimpleMove::Rotate));
+    }
+}
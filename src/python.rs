@@ -0,0 +1,292 @@
+// Python bindings, built with the "python" feature (eg `maturin develop --features python`).
+// They expose the training environment, the board features and the heuristic bot
+// as the `tetrus` Python module, with numpy arrays for the boards.
+use std::time::Duration;
+
+use numpy::{
+    ndarray::{Array1, Array2},
+    IntoPyArray, PyReadonlyArray2,
+};
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+
+use crate::{
+    ai::{GridAnalysis, SearchConfig, TetrisBot},
+    bitboard::BitBoard,
+    constants::{GRID_HEIGHT, GRID_WIDTH},
+    env::{Action, ActionSpace, EnvConfig, EnvObservation, RewardConfig, StepInfo, TetrisEnv},
+    moves::{Move, SimpleMove},
+};
+
+// actions of the "inputs" action space, by index
+// (the counter-clockwise rotation comes last, so that the other inputs keep their index)
+const INPUT_ACTIONS: [Move; 6] = [
+    Move::Simple(SimpleMove::Left),
+    Move::Simple(SimpleMove::Right),
+    Move::Simple(SimpleMove::Down),
+    Move::Simple(SimpleMove::Rotate),
+    Move::HardDrop,
+    Move::Simple(SimpleMove::RotateCounterClockwise),
+];
+const INPUT_NAMES: [&str; 6] = [
+    "left",
+    "right",
+    "down",
+    "rotate",
+    "hard_drop",
+    "rotate_counter_clockwise",
+];
+
+fn to_py_err(err: String) -> PyErr {
+    return PyValueError::new_err(err);
+}
+
+// reads a (20, 10) array of booleans, with row 0 at the top
+fn board_from_array(board: PyReadonlyArray2<bool>) -> PyResult<BitBoard> {
+    let board = board.as_array();
+    if board.dim() != (GRID_HEIGHT as usize, GRID_WIDTH as usize) {
+        return Err(to_py_err(format!(
+            "expected a board of shape ({}, {}), got {:?}",
+            GRID_HEIGHT,
+            GRID_WIDTH,
+            board.dim()
+        )));
+    }
+    let mut bitboard = BitBoard::new();
+    for ((i, j), &is_filled) in board.indexed_iter() {
+        if is_filled {
+            bitboard.set_pixels(&[(i as i16, j as i16)]);
+        }
+    }
+    return Ok(bitboard);
+}
+
+fn observation_to_dict<'py>(
+    py: Python<'py>,
+    observation: EnvObservation,
+) -> PyResult<Bound<'py, PyDict>> {
+    let board = Array2::from_shape_fn(
+        (GRID_HEIGHT as usize, GRID_WIDTH as usize),
+        |(i, j)| observation.board[i][j],
+    );
+    let queue: Array1<i64> = observation
+        .queue
+        .iter()
+        .map(|shape| shape.shape_index() as i64)
+        .collect();
+
+    let dict = PyDict::new(py);
+    dict.set_item("board", board.into_pyarray(py))?;
+    dict.set_item(
+        "column_heights",
+        Array1::from_vec(observation.column_heights.to_vec()).into_pyarray(py),
+    )?;
+    dict.set_item("current_shape", observation.current_shape.shape_index())?;
+    dict.set_item("queue", queue.into_pyarray(py))?;
+    dict.set_item("n_placements", observation.n_placements)?;
+    return Ok(dict);
+}
+
+fn info_to_dict<'py>(py: Python<'py>, info: &StepInfo) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("lines_cleared", info.lines_cleared)?;
+    dict.set_item("total_lines_cleared", info.total_lines_cleared)?;
+    dict.set_item("points", info.points)?;
+    dict.set_item("level", info.level)?;
+    dict.set_item("pieces_placed", info.pieces_placed)?;
+    return Ok(dict);
+}
+
+// Gymnasium-style wrapper around `TetrisEnv`: `step` returns
+// (observation, reward, terminated, truncated, info)
+#[pyclass(name = "TetrisEnv")]
+struct PyTetrisEnv {
+    env: TetrisEnv,
+}
+
+#[pymethods]
+impl PyTetrisEnv {
+    #[new]
+    #[pyo3(signature = (
+        action_space = "placements",
+        max_pieces = None,
+        inputs_per_gravity_drop = 0,
+        line_clear_rewards = None,
+        points_reward = 0.,
+        piece_placed_reward = 0.,
+        holes_reward = 0.,
+        height_reward = 0.,
+        game_over_reward = 0.,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        action_space: &str,
+        max_pieces: Option<usize>,
+        inputs_per_gravity_drop: u32,
+        line_clear_rewards: Option<[f32; 5]>,
+        points_reward: f32,
+        piece_placed_reward: f32,
+        holes_reward: f32,
+        height_reward: f32,
+        game_over_reward: f32,
+    ) -> PyResult<Self> {
+        let action_space = match action_space {
+            "placements" => ActionSpace::Placements,
+            "inputs" => ActionSpace::Inputs,
+            _ => {
+                return Err(to_py_err(format!(
+                    "unknown action space '{}', expected 'placements' or 'inputs'",
+                    action_space
+                )))
+            }
+        };
+        let default_rewards = RewardConfig::default();
+        let config = EnvConfig {
+            action_space,
+            rewards: RewardConfig {
+                line_clears: line_clear_rewards.unwrap_or(default_rewards.line_clears),
+                points: points_reward,
+                piece_placed: piece_placed_reward,
+                holes: holes_reward,
+                height: height_reward,
+                game_over: game_over_reward,
+            },
+            inputs_per_gravity_drop,
+            max_pieces,
+        };
+        return Ok(PyTetrisEnv {
+            env: TetrisEnv::new(config),
+        });
+    }
+
+    // names of the actions of the "inputs" action space, by index
+    #[staticmethod]
+    fn input_names() -> Vec<&'static str> {
+        return INPUT_NAMES.to_vec();
+    }
+
+    #[pyo3(signature = (seed = 0))]
+    fn reset<'py>(&mut self, py: Python<'py>, seed: u64) -> PyResult<Bound<'py, PyDict>> {
+        let observation = self.env.reset(seed);
+        return observation_to_dict(py, observation);
+    }
+
+    fn observation<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        return observation_to_dict(py, self.env.observation());
+    }
+
+    // `action` is an index in `placements()`, or in `input_names()` with the "inputs" action space
+    #[allow(clippy::type_complexity)]
+    fn step<'py>(
+        &mut self,
+        py: Python<'py>,
+        action: usize,
+    ) -> PyResult<(Bound<'py, PyDict>, f32, bool, bool, Bound<'py, PyDict>)> {
+        let action = match self.env.config().action_space {
+            ActionSpace::Placements => Action::Placement(action),
+            ActionSpace::Inputs => Action::Input(*INPUT_ACTIONS.get(action).ok_or_else(|| {
+                to_py_err(format!(
+                    "input index {} out of range (0..{})",
+                    action,
+                    INPUT_ACTIONS.len()
+                ))
+            })?),
+        };
+        let result = self.env.step(action).map_err(to_py_err)?;
+        let truncated = result.info.truncated;
+        return Ok((
+            observation_to_dict(py, result.observation)?,
+            result.reward,
+            result.done && !truncated,
+            truncated,
+            info_to_dict(py, &result.info)?,
+        ));
+    }
+
+    // final position of the current piece for each placement, as (row, column, rotation)
+    fn placements(&self) -> Vec<(i16, i16, usize)> {
+        return self
+            .env
+            .placements()
+            .iter()
+            .map(|placement| {
+                let (row, col) = placement.shape.origin();
+                return (row, col, placement.shape.rotation_index);
+            })
+            .collect();
+    }
+}
+
+// The search-based bot of the game, choosing placements for a `TetrisEnv`
+#[pyclass(name = "HeuristicBot")]
+struct PyHeuristicBot {
+    config: SearchConfig,
+}
+
+#[pymethods]
+impl PyHeuristicBot {
+    #[new]
+    #[pyo3(signature = (lookahead = 1, beam_width = 5, expect_unknown = false, think_ms = None))]
+    fn new(
+        lookahead: usize,
+        beam_width: usize,
+        expect_unknown: bool,
+        think_ms: Option<u64>,
+    ) -> Self {
+        return PyHeuristicBot {
+            config: SearchConfig {
+                lookahead,
+                beam_width,
+                expect_unknown_piece: expect_unknown,
+                time_budget: think_ms.map(Duration::from_millis),
+            },
+        };
+    }
+
+    // index of the chosen placement in `env.placements()`, or None if there is none
+    fn choose_placement(&self, py: Python<'_>, env: &PyTetrisEnv) -> Option<usize> {
+        let engine = env.env.engine();
+        let board = env.env.board();
+        let current_shape = *engine.current_shape();
+        let preview = engine.preview().to_vec();
+        // the search runs on the rayon pool, so let other Python threads run meanwhile
+        return py.detach(|| {
+            TetrisBot::choose_placement(&board, &current_shape, &preview, &self.config)
+        });
+    }
+}
+
+// row index (from the top) of the highest filled cell, or 20 on an empty board
+#[pyfunction]
+fn first_nonempty_row_index(board: PyReadonlyArray2<bool>) -> PyResult<usize> {
+    return Ok(GridAnalysis::get_first_nonempty_row_index(
+        &board_from_array(board)?,
+    ));
+}
+
+#[pyfunction]
+fn count_filled_rows(board: PyReadonlyArray2<bool>) -> PyResult<usize> {
+    return Ok(GridAnalysis::count_filled_rows(&board_from_array(board)?));
+}
+
+// variance of the heights of the non-empty columns
+#[pyfunction]
+fn col_height_variance(board: PyReadonlyArray2<bool>) -> PyResult<f32> {
+    return Ok(GridAnalysis::col_height_variance(&board_from_array(board)?));
+}
+
+// number of empty cells with a filled cell above them
+#[pyfunction]
+fn count_gaps(board: PyReadonlyArray2<bool>) -> PyResult<usize> {
+    return Ok(GridAnalysis::count_gaps(&board_from_array(board)?));
+}
+
+#[pymodule]
+fn tetrus(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyTetrisEnv>()?;
+    module.add_class::<PyHeuristicBot>()?;
+    module.add_function(wrap_pyfunction!(first_nonempty_row_index, module)?)?;
+    module.add_function(wrap_pyfunction!(count_filled_rows, module)?)?;
+    module.add_function(wrap_pyfunction!(col_height_variance, module)?)?;
+    module.add_function(wrap_pyfunction!(count_gaps, module)?)?;
+    return Ok(());
+}
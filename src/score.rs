@@ -1,13 +1,17 @@
-use std::time::Duration;
-
 use macroquad::{text::draw_text, prelude::WHITE};
 
-use crate::constants::{GRID_WIDTH, BLOCK_SIZE};
+use crate::{
+    constants::{BLOCK_SIZE, GRID_WIDTH},
+    modes::GameMode,
+};
 
+#[derive(Clone)]
 pub struct Score {
     pub points: i32,
     pub level: i32,
     pub total_lines_cleared: i32,
+    // level of the game before any line got cleared
+    pub start_level: i32,
 }
 
 impl Score {
@@ -16,15 +20,17 @@ impl Score {
     const TOP_OFFSET: f32 = 200.;
     const MARGIN_BETWEEN_STATS: f32 = 40.;
 
-    pub fn new() -> Self {
+    pub fn new(start_level: i32) -> Self {
         Score {
             points: 0,
-            level: 0,
+            level: start_level,
             total_lines_cleared: 0,
+            start_level,
         }
     }
 
-    pub fn update(&mut self, n_lines_cleared: i32) -> bool {
+    // the mode decides of the level reached after the line clear
+    pub fn update(&mut self, n_lines_cleared: i32, mode: &dyn GameMode) -> bool {
         let mut base = 0;
         if n_lines_cleared == 0 {
             return false;
@@ -39,13 +45,13 @@ impl Score {
         self.points += base * (self.level + 1);
 
         self.total_lines_cleared += n_lines_cleared;
-        let curr_level = self.total_lines_cleared / 10;
+        let curr_level = mode.level(self.start_level, self.total_lines_cleared);
         let did_level_up = curr_level > self.level;
         self.level = curr_level;
         return did_level_up;
     }
 
-    fn draw_text_at(&self, text: &str, position: u8) {
+    pub fn draw_text_at(&self, text: &str, position: u8) {
         draw_text(
             text,
             Score::LEFT_PADDING,
@@ -60,12 +66,4 @@ impl Score {
         self.draw_text_at(&format!("Level: {}", self.level), 1);
         self.draw_text_at(&format!("Lines cleared: {}", self.total_lines_cleared), 2);
     }
-
-    pub fn get_block_drop_delay(&self) -> Duration {
-        // from https://tetris.wiki/Marathon
-        return Duration::from_secs_f64(f64::powi(
-            0.8 - (self.level as f64 - 1.) * 0.007,
-            self.level - 1,
-        ));
-    }
 }
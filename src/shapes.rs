@@ -1,7 +1,14 @@
-use macroquad::prelude::{YELLOW, ORANGE, BLUE, PURPLE, GREEN, RED, WHITE};
-use rand::Rng;
+use macroquad::{
+    prelude::{YELLOW, ORANGE, BLUE, PURPLE, GREEN, RED, WHITE},
+    shapes::draw_rectangle,
+    text::draw_text,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::{constants::GRID_WIDTH, moves::SimpleMove};
+use crate::{
+    constants::{BLOCK_SIZE, CELL_BORDER, GRID_WIDTH},
+    moves::SimpleMove,
+};
 
 
 pub const SHAPE_COLORS: [macroquad::prelude::Color; 7] =
@@ -20,62 +27,189 @@ pub fn index_to_color_name(index: usize) -> String {
     };
     return res.to_string();
 }
-pub fn get_shapes() -> [Vec<[(i16, i16); 4]>; 6] {
-    return [
-        // square
-        vec![[(0, 0), (0, 1), (1, 1), (1, 0)]],
-        // straight
-        vec![
-            [(0, 0), (0, 1), (0, 2), (0, 3)],
-            [(-1, 1), (0, 1), (1, 1), (2, 1)],
-        ],
-        // T-shape
-        vec![
-            [(0, 0), (0, 1), (0, 2), (1, 1)],
-            [(0, 0), (0, 1), (-1, 1), (1, 1)],
-            [(0, 0), (0, 1), (0, 2), (-1, 1)],
-            [(0, 1), (-1, 1), (1, 1), (0, 2)],
-        ],
-        // Skew 1
-        vec![
-            [(0, 0), (0, 1), (1, 1), (1, 2)],
-            [(0, 1), (0, 2), (-1, 2), (1, 1)],
-        ],
-        // Skew 2,
-        vec![
-            [(0, 1), (0, 2), (1, 1), (1, 0)],
-            [(0, 1), (-1, 1), (0, 2), (1, 2)],
-        ],
-        // L shape 1
-        vec![
-            [(0, 0), (0, 1), (0, 2), (1, 0)],
-            [(-1, 0), (-1, 1), (0, 1), (1, 1)],
-            [(0, 0), (0, 1), (0, 2), (-1, 2)],
-            [(-1, 1), (0, 1), (1, 1), (1, 2)],
-        ],
-    ];
+
+pub const N_SHAPES: usize = 7;
+
+// static so that looking up a piece's pixels does not allocate
+// (the bot search does it millions of times)
+static SHAPES: [&[[(i16, i16); 4]]; N_SHAPES] = [
+    // square
+    &[[(0, 0), (0, 1), (1, 1), (1, 0)]],
+    // straight
+    &[
+        [(0, 0), (0, 1), (0, 2), (0, 3)],
+        [(-1, 1), (0, 1), (1, 1), (2, 1)],
+    ],
+    // T-shape
+    &[
+        [(0, 0), (0, 1), (0, 2), (1, 1)],
+        [(0, 0), (0, 1), (-1, 1), (1, 1)],
+        [(0, 0), (0, 1), (0, 2), (-1, 1)],
+        [(0, 1), (-1, 1), (1, 1), (0, 2)],
+    ],
+    // Skew 1
+    &[
+        [(0, 0), (0, 1), (1, 1), (1, 2)],
+        [(0, 1), (0, 2), (-1, 2), (1, 1)],
+    ],
+    // Skew 2,
+    &[
+        [(0, 1), (0, 2), (1, 1), (1, 0)],
+        [(0, 1), (-1, 1), (0, 2), (1, 2)],
+    ],
+    // L shape 1
+    &[
+        [(0, 0), (0, 1), (0, 2), (1, 0)],
+        [(-1, 0), (-1, 1), (0, 1), (1, 1)],
+        [(0, 0), (0, 1), (0, 2), (-1, 2)],
+        [(-1, 1), (0, 1), (1, 1), (1, 2)],
+    ],
+    // L shape 2
+    &[
+        [(0, 0), (0, 1), (0, 2), (1, 2)],
+        [(-1, 1), (0, 1), (1, 0), (1, 1)],
+        [(-1, 0), (0, 0), (0, 1), (0, 2)],
+        [(-1, 1), (-1, 2), (0, 1), (1, 1)],
+    ],
+];
+
+// Same shapes and rotation states, rotating around the same center as on the NES
+// (where the reference point of a piece is its center of rotation)
+static NES_SHAPES: [&[[(i16, i16); 4]]; N_SHAPES] = [
+    // square
+    &[[(0, -1), (0, 0), (1, -1), (1, 0)]],
+    // straight
+    &[
+        [(0, -2), (0, -1), (0, 0), (0, 1)],
+        [(-2, 0), (-1, 0), (0, 0), (1, 0)],
+    ],
+    // T-shape
+    &[
+        [(0, -1), (0, 0), (0, 1), (1, 0)],
+        [(-1, 0), (0, -1), (0, 0), (1, 0)],
+        [(0, -1), (0, 0), (0, 1), (-1, 0)],
+        [(-1, 0), (0, 0), (0, 1), (1, 0)],
+    ],
+    // Skew 1
+    &[
+        [(0, -1), (0, 0), (1, 0), (1, 1)],
+        [(-1, 1), (0, 0), (0, 1), (1, 0)],
+    ],
+    // Skew 2
+    &[
+        [(0, 0), (0, 1), (1, -1), (1, 0)],
+        [(-1, 0), (0, 0), (0, 1), (1, 1)],
+    ],
+    // L shape 1
+    &[
+        [(0, -1), (0, 0), (0, 1), (1, -1)],
+        [(-1, -1), (-1, 0), (0, 0), (1, 0)],
+        [(-1, 1), (0, -1), (0, 0), (0, 1)],
+        [(-1, 0), (0, 0), (1, 0), (1, 1)],
+    ],
+    // L shape 2
+    &[
+        [(0, -1), (0, 0), (0, 1), (1, 1)],
+        [(-1, 0), (0, 0), (1, -1), (1, 0)],
+        [(-1, -1), (0, -1), (0, 0), (0, 1)],
+        [(-1, 0), (-1, 1), (0, 0), (1, 0)],
+    ],
+];
+
+pub fn get_shapes() -> &'static [&'static [[(i16, i16); 4]]; N_SHAPES] {
+    return &SHAPES;
+}
+
+// how the pieces turn: neither system has any wall kick
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RotationSystem {
+    Standard,
+    Nes,
+}
+
+impl RotationSystem {
+    fn shapes(&self) -> &'static [&'static [[(i16, i16); 4]]; N_SHAPES] {
+        return match self {
+            RotationSystem::Standard => &SHAPES,
+            RotationSystem::Nes => &NES_SHAPES,
+        };
+    }
+
+    // cell the pieces turn around, relative to their origin (part of the piece in every rotation)
+    fn center(&self) -> (i16, i16) {
+        return match self {
+            RotationSystem::Standard => (0, 1),
+            RotationSystem::Nes => (0, 0),
+        };
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+// how the next piece gets picked
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Randomizer {
+    // any piece, with the same odds
+    Uniform,
+    // rerolls once if it picks the previous piece (or a dummy value), like the NES
+    Nes,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct ShapePosition {
     pos: (i16, i16),
     shape_index: usize,
     pub rotation_index: usize,
     pub color_index: usize,
+    rotation_system: RotationSystem,
 }
 
 impl ShapePosition {
-    pub fn new() -> Self {
+    pub fn random(rng: &mut impl Rng) -> Self {
+        let mut shape = Self::with_shape(rng.gen_range(0..N_SHAPES));
+        shape.color_index = rng.gen_range(0..SHAPE_COLORS.len());
+        return shape;
+    }
+
+    // a piece of the given kind, at its spawn position
+    pub fn with_shape(shape_index: usize) -> Self {
         ShapePosition {
             pos: (0, (GRID_WIDTH / 2) as i16), // topleft pixel index ("offset" ?)
-            shape_index: rand::thread_rng().gen_range(0..6),
+            shape_index,
             rotation_index: 0,
-            color_index: rand::thread_rng().gen_range(0..SHAPE_COLORS.len()),
+            color_index: 0,
+            rotation_system: RotationSystem::Standard,
         }
     }
 
-    pub fn n_rotations(&self) -> usize {
-        return get_shapes()[self.shape_index].len();
+    pub fn with_rotation_system(mut self, rotation_system: RotationSystem) -> Self {
+        self.rotation_system = rotation_system;
+        return self;
+    }
+
+    // pixels of the piece in its spawn rotation, relative to its top-left corner
+    pub fn get_preview_pixels(&self) -> [(i16, i16); 4] {
+        let mut pixels = self.rotation_system.shapes()[self.shape_index][0];
+        let top = pixels.iter().map(|(i, _)| *i).min().unwrap();
+        let left = pixels.iter().map(|(_, j)| *j).min().unwrap();
+        for pixel in pixels.iter_mut() {
+            *pixel = (pixel.0 - top, pixel.1 - left);
+        }
+        return pixels;
+    }
+
+    // index of the piece's kind in `get_shapes`
+    pub fn shape_index(&self) -> usize {
+        return self.shape_index;
+    }
+
+    // row and column of the piece's reference point
+    pub fn origin(&self) -> (i16, i16) {
+        return self.pos;
+    }
+
+    // row and column of the cell the piece turns around
+    pub fn rotation_center(&self) -> (i16, i16) {
+        let (di, dj) = self.rotation_system.center();
+        return (self.pos.0 + di, self.pos.1 + dj);
     }
 
     pub fn moved_to(&self, move_: &SimpleMove) -> Self {
@@ -85,23 +219,19 @@ impl ShapePosition {
             SimpleMove::Right => new_pos.pos.1 += 1,
             SimpleMove::Down => new_pos.pos.0 += 1,
             SimpleMove::Rotate => {
-                let n_rotations = get_shapes()[new_pos.shape_index].len();
+                let n_rotations = self.rotation_system.shapes()[new_pos.shape_index].len();
                 new_pos.rotation_index = (new_pos.rotation_index + 1) % n_rotations
             }
+            SimpleMove::RotateCounterClockwise => {
+                let n_rotations = self.rotation_system.shapes()[new_pos.shape_index].len();
+                new_pos.rotation_index = (new_pos.rotation_index + n_rotations - 1) % n_rotations
+            }
         }
         return new_pos;
     }
 
-    pub fn n_moves(&self, horizontal_shift: i16, n_rotations: usize, vertical_shift: u16) -> Self {
-        let mut new_pos = self.clone();
-        new_pos.rotation_index += n_rotations;
-        new_pos.pos.1 += horizontal_shift;
-        new_pos.pos.0 += vertical_shift as i16;
-        return new_pos;
-    }
-
     pub fn get_pixels(&self) -> [(i16, i16); 4] {
-        let shape_pixels = get_shapes()[self.shape_index][self.rotation_index];
+        let shape_pixels = self.rotation_system.shapes()[self.shape_index][self.rotation_index];
         let mut result = [(0, 0); 4];
         let mut idx = 0;
         for pixel in shape_pixels {
@@ -112,4 +242,111 @@ impl ShapePosition {
         }
         return result;
     }
-}
\ No newline at end of file
+}
+
+// The pieces coming after the current one, which are shown to the player (and the bot).
+// Pieces are drawn from a seeded generator, so that games can be replayed.
+pub struct ShapeQueue {
+    upcoming: Vec<ShapePosition>,
+    rng: StdRng,
+    randomizer: Randomizer,
+    rotation_system: RotationSystem,
+    // last piece picked, for the NES randomizer
+    last_shape_index: Option<usize>,
+}
+
+impl ShapeQueue {
+    const LEFT_PADDING: f32 = GRID_WIDTH as f32 * BLOCK_SIZE as f32 + 100f32;
+    const TOP_OFFSET: f32 = 360.;
+    const PREVIEW_BLOCK_SIZE: f32 = BLOCK_SIZE as f32 / 2.;
+
+    pub fn new(
+        preview_size: usize,
+        seed: u64,
+        randomizer: Randomizer,
+        rotation_system: RotationSystem,
+    ) -> Self {
+        let mut queue = ShapeQueue {
+            upcoming: Vec::with_capacity(preview_size + 1),
+            rng: StdRng::seed_from_u64(seed),
+            randomizer,
+            rotation_system,
+            last_shape_index: None,
+        };
+        for _ in 0..preview_size {
+            let shape = queue.random_shape();
+            queue.upcoming.push(shape);
+        }
+        return queue;
+    }
+
+    fn random_shape(&mut self) -> ShapePosition {
+        let shape = match self.randomizer {
+            Randomizer::Uniform => ShapePosition::random(&mut self.rng),
+            Randomizer::Nes => {
+                // the dummy value stands for the 8th roll of the NES, among its 7 pieces
+                let mut shape_index = self.rng.gen_range(0..N_SHAPES + 1);
+                if shape_index == N_SHAPES || Some(shape_index) == self.last_shape_index {
+                    shape_index = self.rng.gen_range(0..N_SHAPES);
+                }
+                let mut shape = ShapePosition::with_shape(shape_index);
+                shape.color_index = self.rng.gen_range(0..SHAPE_COLORS.len());
+                shape
+            }
+        };
+        self.last_shape_index = Some(shape.shape_index);
+        return shape.with_rotation_system(self.rotation_system);
+    }
+
+    pub fn pop(&mut self) -> ShapePosition {
+        let shape = self.random_shape();
+        self.upcoming.push(shape);
+        return self.upcoming.remove(0);
+    }
+
+    pub fn preview(&self) -> &[ShapePosition] {
+        return &self.upcoming;
+    }
+
+    pub fn draw(&self) {
+        if self.upcoming.is_empty() {
+            return;
+        }
+        draw_text("Next:", Self::LEFT_PADDING, Self::TOP_OFFSET, 40., WHITE);
+        for (slot, shape) in self.upcoming.iter().enumerate() {
+            // leave room for 3 rows per piece, plus some spacing
+            let top = Self::TOP_OFFSET + 20. + slot as f32 * 4. * Self::PREVIEW_BLOCK_SIZE;
+            for (i, j) in shape.get_preview_pixels() {
+                draw_rectangle(
+                    Self::LEFT_PADDING + j as f32 * Self::PREVIEW_BLOCK_SIZE + CELL_BORDER,
+                    top + i as f32 * Self::PREVIEW_BLOCK_SIZE + CELL_BORDER,
+                    Self::PREVIEW_BLOCK_SIZE - CELL_BORDER,
+                    Self::PREVIEW_BLOCK_SIZE - CELL_BORDER,
+                    SHAPE_COLORS[shape.color_index],
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotations_go_both_ways_around_the_center() {
+        for rotation_system in [RotationSystem::Standard, RotationSystem::Nes] {
+            for shape_index in 0..N_SHAPES {
+                let mut shape =
+                    ShapePosition::with_shape(shape_index).with_rotation_system(rotation_system);
+                for _ in 0..rotation_system.shapes()[shape_index].len() {
+                    assert!(shape.get_pixels().contains(&shape.rotation_center()));
+                    let turned = shape.moved_to(&SimpleMove::Rotate);
+                    assert_eq!(turned.moved_to(&SimpleMove::RotateCounterClockwise), shape);
+                    shape = turned;
+                }
+                assert_eq!(shape.rotation_index, 0);
+            }
+        }
+    }
+}
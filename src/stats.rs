@@ -1,13 +1,23 @@
+// Summary statistics, for the CLI summaries and the benchmark reports.
+// Every function accepts empty inputs, for which it returns 0.
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
 
-pub fn avg(vals: &Vec<i32>) -> f32 {
+pub fn avg(vals: &[i32]) -> f32 {
+    if vals.is_empty() {
+        return 0.;
+    }
     return vals.iter().sum::<i32>() as f32 / vals.len() as f32;
 }
 
-pub fn float_avg(vals: &Vec<f32>) -> f32 {
+pub fn float_avg(vals: &[f32]) -> f32 {
+    if vals.is_empty() {
+        return 0.;
+    }
     return vals.iter().sum::<f32>() / vals.len() as f32;
 }
 
-pub fn variance(vals: &Vec<i32>) -> f32 {
+pub fn variance(vals: &[i32]) -> f32 {
     let avg_vals = avg(vals);
     let squared_diffs = vals
         .iter()
@@ -16,10 +26,430 @@ pub fn variance(vals: &Vec<i32>) -> f32 {
     return float_avg(&squared_diffs);
 }
 
-pub fn sd(vals: &Vec<i32>) -> f32 {
+pub fn sd(vals: &[i32]) -> f32 {
     return variance(vals).sqrt();
 }
 
-pub fn summarize(vals: &Vec<i32>) -> String {
-    return format!("{} ± {}", avg(vals), sd(vals));
-}
\ No newline at end of file
+pub fn summarize(vals: &[i32]) -> String {
+    return float_summarize(&vals.iter().map(|&v| v as f32).collect::<Vec<f32>>());
+}
+
+pub fn float_summarize(vals: &[f32]) -> String {
+    let summary = Summary::new(vals);
+    if summary.n == 0 {
+        return "no data".to_string();
+    }
+    return format!(
+        "{} ± {} (median {}, range {} to {})",
+        summary.mean, summary.sd, summary.median, summary.min, summary.max
+    );
+}
+
+// `q`-th quantile (`q` between 0 and 1), interpolating linearly between the closest values
+pub fn quantile(vals: &[f32], q: f32) -> f32 {
+    if vals.is_empty() {
+        return 0.;
+    }
+    let mut sorted = vals.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = q.clamp(0., 1.) * (sorted.len() - 1) as f32;
+    let below = rank.floor() as usize;
+    let above = rank.ceil() as usize;
+    return sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f32);
+}
+
+pub fn median(vals: &[f32]) -> f32 {
+    return quantile(vals, 0.5);
+}
+
+// sample standard deviation
+pub fn float_sd(vals: &[f32]) -> f32 {
+    if vals.len() < 2 {
+        return 0.;
+    }
+    let avg_vals = float_avg(vals);
+    let sum_squared_diffs = vals.iter().map(|v| (v - avg_vals) * (v - avg_vals)).sum::<f32>();
+    return (sum_squared_diffs / (vals.len() - 1) as f32).sqrt();
+}
+
+// 95% confidence interval of the mean (normal approximation)
+pub fn mean_confidence_interval(vals: &[f32]) -> (f32, f32) {
+    let avg_vals = float_avg(vals);
+    if vals.is_empty() {
+        return (avg_vals, avg_vals);
+    }
+    let half_width = 1.96 * float_sd(vals) / (vals.len() as f32).sqrt();
+    return (avg_vals - half_width, avg_vals + half_width);
+}
+
+// Confidence interval of the mean, with the percentile bootstrap: it makes no assumption
+// on the distribution, which matters for skewed values like game scores.
+// The resampling is seeded, so that a report always gives the same interval.
+pub fn bootstrap_mean_ci(vals: &[f32], confidence: f32, n_resamples: usize) -> (f32, f32) {
+    if vals.is_empty() {
+        return (0., 0.);
+    }
+    let mut rng = StdRng::seed_from_u64(0);
+    let means: Vec<f32> = (0..n_resamples)
+        .map(|_| {
+            let sum = (0..vals.len())
+                .map(|_| vals[rng.gen_range(0..vals.len())] as f64)
+                .sum::<f64>();
+            return (sum / vals.len() as f64) as f32;
+        })
+        .collect();
+    let tail = (1. - confidence) / 2.;
+    return (quantile(&means, tail), quantile(&means, 1. - tail));
+}
+
+// Running count, mean, variance and range, updated one value at a time (Welford's algorithm),
+// for when the values are not all kept in memory.
+// Accumulators can be merged, eg to combine the results of parallel workers.
+#[derive(Clone, Copy, Debug)]
+pub struct Welford {
+    n: usize,
+    mean: f64,
+    // sum of the squared differences to the mean
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Welford {
+    pub fn new() -> Self {
+        return Welford {
+            n: 0,
+            mean: 0.,
+            m2: 0.,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        };
+    }
+
+    pub fn push(&mut self, val: f32) {
+        let val = val as f64;
+        self.n += 1;
+        let delta = val - self.mean;
+        self.mean += delta / self.n as f64;
+        self.m2 += delta * (val - self.mean);
+        self.min = self.min.min(val);
+        self.max = self.max.max(val);
+    }
+
+    pub fn merge(&mut self, other: &Welford) {
+        if other.n == 0 {
+            return;
+        }
+        let n = self.n + other.n;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.n as f64 / n as f64;
+        self.m2 += other.m2 + delta * delta * (self.n * other.n) as f64 / n as f64;
+        self.n = n;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn from_values(vals: &[f32]) -> Self {
+        let mut welford = Welford::new();
+        for &val in vals {
+            welford.push(val);
+        }
+        return welford;
+    }
+
+    pub fn count(&self) -> usize {
+        return self.n;
+    }
+
+    pub fn mean(&self) -> f32 {
+        return self.mean as f32;
+    }
+
+    // sample variance
+    pub fn variance(&self) -> f32 {
+        if self.n < 2 {
+            return 0.;
+        }
+        return (self.m2 / (self.n - 1) as f64) as f32;
+    }
+
+    pub fn sd(&self) -> f32 {
+        return self.variance().sqrt();
+    }
+
+    pub fn min(&self) -> f32 {
+        return if self.n == 0 { 0. } else { self.min as f32 };
+    }
+
+    pub fn max(&self) -> f32 {
+        return if self.n == 0 { 0. } else { self.max as f32 };
+    }
+}
+
+impl Default for Welford {
+    fn default() -> Self {
+        return Welford::new();
+    }
+}
+
+// Counts of values in bins of equal width, from the smallest to the largest value
+#[derive(Serialize, Clone, Debug)]
+pub struct Histogram {
+    pub min: f32,
+    pub bin_width: f32,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    pub fn new(vals: &[f32], n_bins: usize) -> Self {
+        let min = quantile(vals, 0.);
+        let max = quantile(vals, 1.);
+        let n_bins = n_bins.max(1);
+        // a single bin when all the values are equal
+        let bin_width = if max > min { (max - min) / n_bins as f32 } else { 1. };
+        let mut counts = vec![0; if max > min { n_bins } else { 1 }];
+        for val in vals {
+            // the largest value goes in the last bin
+            let bin = (((val - min) / bin_width) as usize).min(counts.len() - 1);
+            counts[bin] += 1;
+        }
+        return Histogram {
+            min,
+            bin_width,
+            counts,
+        };
+    }
+
+    // one line per bin, with a bar of up to `bar_width` characters
+    pub fn render(&self, bar_width: usize) -> String {
+        let max_count = self.counts.iter().max().copied().unwrap_or(0).max(1);
+        return self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                let low = self.min + i as f32 * self.bin_width;
+                return format!(
+                    "{:>10.1} - {:<10.1} {:>6} {}",
+                    low,
+                    low + self.bin_width,
+                    count,
+                    "#".repeat(count * bar_width / max_count)
+                );
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+    }
+}
+
+// Distribution of a metric over many games
+#[derive(Serialize, Clone, Debug)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f32,
+    pub sd: f32,
+    // 95% bootstrap confidence interval of the mean
+    pub ci_low: f32,
+    pub ci_high: f32,
+    pub min: f32,
+    pub p10: f32,
+    pub p25: f32,
+    pub median: f32,
+    pub p75: f32,
+    pub p90: f32,
+    pub max: f32,
+}
+
+impl Summary {
+    const N_BOOTSTRAP_RESAMPLES: usize = 1000;
+
+    pub fn new(vals: &[f32]) -> Self {
+        let welford = Welford::from_values(vals);
+        let (ci_low, ci_high) = bootstrap_mean_ci(vals, 0.95, Self::N_BOOTSTRAP_RESAMPLES);
+        return Summary {
+            n: welford.count(),
+            mean: welford.mean(),
+            sd: welford.sd(),
+            ci_low,
+            ci_high,
+            min: welford.min(),
+            p10: quantile(vals, 0.1),
+            p25: quantile(vals, 0.25),
+            median: median(vals),
+            p75: quantile(vals, 0.75),
+            p90: quantile(vals, 0.9),
+            max: welford.max(),
+        };
+    }
+}
+
+// Result of a two-sided paired t-test on the differences `b - a`
+pub struct PairedTest {
+    pub mean_diff: f32,
+    pub median_diff: f32,
+    // 95% confidence interval of the mean difference
+    pub ci_low: f32,
+    pub ci_high: f32,
+    // number of pairs where b is higher, equal and lower than a
+    pub n_higher: usize,
+    pub n_ties: usize,
+    pub n_lower: usize,
+    pub t: f32,
+    pub p_value: f32,
+}
+
+pub fn paired_t_test(a: &[f32], b: &[f32]) -> PairedTest {
+    let diffs: Vec<f32> = a.iter().zip(b).map(|(a, b)| b - a).collect();
+    let mean_diff = float_avg(&diffs);
+    let standard_error = float_sd(&diffs) / (diffs.len() as f32).sqrt();
+    let (ci_low, ci_high) = if diffs.len() < 2 {
+        (mean_diff, mean_diff)
+    } else {
+        let dof = (diffs.len() - 1) as f64;
+        let half_width = student_t_critical_value(dof, 0.95) as f32 * standard_error;
+        (mean_diff - half_width, mean_diff + half_width)
+    };
+    let (t, p_value) = if standard_error > 0. {
+        let t = mean_diff / standard_error;
+        (t, student_t_p_value(t as f64, (diffs.len() - 1) as f64) as f32)
+    } else if mean_diff != 0. {
+        // every pair differs by the same amount
+        (mean_diff.signum() * f32::INFINITY, 0.)
+    } else {
+        (0., 1.)
+    };
+    return PairedTest {
+        mean_diff,
+        median_diff: median(&diffs),
+        ci_low,
+        ci_high,
+        n_higher: diffs.iter().filter(|&&diff| diff > 0.).count(),
+        n_ties: diffs.iter().filter(|&&diff| diff == 0.).count(),
+        n_lower: diffs.iter().filter(|&&diff| diff < 0.).count(),
+        t,
+        p_value,
+    };
+}
+
+// two-sided p-value of a Student t statistic with `dof` degrees of freedom
+fn student_t_p_value(t: f64, dof: f64) -> f64 {
+    return regularized_incomplete_beta(dof / (dof + t * t), dof / 2., 0.5);
+}
+
+// value that a Student t statistic with `dof` degrees of freedom exceeds (in absolute value)
+// with a probability of `1 - confidence`, found by bisection
+fn student_t_critical_value(dof: f64, confidence: f64) -> f64 {
+    let (mut low, mut high) = (0., 1e6);
+    for _ in 0..100 {
+        let mid = (low + high) / 2.;
+        if student_t_p_value(mid, dof) > 1. - confidence {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    return (low + high) / 2.;
+}
+
+// log of the gamma function (Lanczos approximation)
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let mut series = 1.000000000190015;
+    for (i, coeff) in COEFFS.iter().enumerate() {
+        series += coeff / (x + 1. + i as f64);
+    }
+    return -tmp + (2.5066282746310005 * series / x).ln();
+}
+
+// I_x(a, b), evaluated with its continued fraction (Numerical Recipes' betacf)
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0. {
+        return 0.;
+    }
+    if x >= 1. {
+        return 1.;
+    }
+    // the continued fraction converges quickly for x < (a + 1) / (a + b + 2)
+    if x > (a + 1.) / (a + b + 2.) {
+        return 1. - regularized_incomplete_beta(1. - x, b, a);
+    }
+    let ln_front = ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1. - x).ln();
+
+    const TINY: f64 = 1e-300;
+    let mut c = 1.;
+    let mut d = 1. - (a + b) * x / (a + 1.);
+    d = 1. / if d.abs() < TINY { TINY } else { d };
+    let mut fraction = d;
+    for m in 1..300 {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2. * m - 1.) * (a + 2. * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2. * m) * (a + 2. * m + 1.)),
+        ] {
+            d = 1. + numerator * d;
+            d = 1. / if d.abs() < TINY { TINY } else { d };
+            c = 1. + numerator / c;
+            c = if c.abs() < TINY { TINY } else { c };
+            fraction *= d * c;
+        }
+        if (d * c - 1.).abs() < 1e-12 {
+            break;
+        }
+    }
+    return ln_front.exp() * fraction / a;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(value: f64, expected: f64) {
+        assert!(
+            (value - expected).abs() < 1e-4,
+            "{} instead of {}",
+            value,
+            expected
+        );
+    }
+
+    // with 1 and 2 degrees of freedom, the p-values have closed forms
+    #[test]
+    fn student_t_p_values() {
+        for t in [0.5, 1., 2., 10.] {
+            assert_close(
+                student_t_p_value(t, 1.),
+                1. - 2. * f64::atan(t) / std::f64::consts::PI,
+            );
+            assert_close(student_t_p_value(t, 2.), 1. - t / f64::sqrt(2. + t * t));
+        }
+        assert_close(student_t_p_value(0., 5.), 1.);
+    }
+
+    #[test]
+    fn student_t_critical_values() {
+        // from the tables, for a 95% confidence
+        for (dof, critical_value) in [(1., 12.7062), (2., 4.3027), (4., 2.7764), (9., 2.2622)] {
+            assert!((student_t_critical_value(dof, 0.95) - critical_value).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn paired_t_test_on_three_pairs() {
+        // differences of 1, 2 and 4: t = sqrt(7) with 2 degrees of freedom
+        let test = paired_t_test(&[0., 0., 0.], &[1., 2., 4.]);
+        assert_close(test.t as f64, 7f64.sqrt());
+        assert_close(test.p_value as f64, 1. - 7f64.sqrt() / 3.);
+        let half_width = 4.3027 * (7f64 / 3.).sqrt() / 3f64.sqrt();
+        assert!((test.ci_low as f64 - (7. / 3. - half_width)).abs() < 1e-3);
+        assert!((test.ci_high as f64 - (7. / 3. + half_width)).abs() < 1e-3);
+        assert_eq!((test.n_higher, test.n_ties, test.n_lower), (3, 0, 0));
+    }
+}
@@ -0,0 +1,362 @@
+// Backend for bots speaking the Tetris Bot Protocol (https://github.com/tetris-bot-protocol/tbp-spec):
+// the bot runs as a child process, and we exchange one JSON message per line on its stdin/stdout.
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agents::{Agent, Observation},
+    bitboard::BitBoard,
+    constants::{GRID_HEIGHT, GRID_WIDTH},
+    moves::Move,
+    placements::enumerate_placements,
+    shapes::ShapePosition,
+};
+
+// TBP boards are 40 rows high, the bottom 20 of which are the visible playfield
+const TBP_BOARD_HEIGHT: usize = 40;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct PieceLocation {
+    #[serde(rename = "type")]
+    piece: String,
+    orientation: String,
+    x: i16,
+    y: i16,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct TbpMove {
+    location: PieceLocation,
+    spin: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules {
+        randomizer: String,
+    },
+    Start {
+        hold: Option<String>,
+        queue: Vec<String>,
+        combo: u32,
+        back_to_back: bool,
+        board: Vec<Vec<Option<String>>>,
+    },
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        move_: TbpMove,
+    },
+    NewPiece {
+        piece: String,
+    },
+    Stop,
+    Quit,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Info {
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        version: String,
+        #[serde(default)]
+        author: String,
+    },
+    Ready,
+    Error {
+        #[serde(default)]
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<TbpMove>,
+    },
+}
+
+// TBP name of each of our shapes (in the order of `get_shapes`)
+const PIECE_NAMES: [&str; 7] = ["O", "I", "T", "Z", "S", "L", "J"];
+
+fn piece_name(shape: &ShapePosition) -> String {
+    return PIECE_NAMES[shape.shape_index()].to_string();
+}
+
+// Cells of a piece in the TBP (SRS) north orientation, relative to its rotation center.
+// x goes right and y goes up.
+fn north_cells(piece: &str) -> Option<[(i16, i16); 4]> {
+    return match piece {
+        "I" => Some([(-1, 0), (0, 0), (1, 0), (2, 0)]),
+        "O" => Some([(0, 0), (1, 0), (0, 1), (1, 1)]),
+        "T" => Some([(-1, 0), (0, 0), (1, 0), (0, 1)]),
+        "L" => Some([(-1, 0), (0, 0), (1, 0), (1, 1)]),
+        "J" => Some([(-1, 0), (0, 0), (1, 0), (-1, 1)]),
+        "S" => Some([(-1, 0), (0, 0), (0, 1), (1, 1)]),
+        "Z" => Some([(-1, 1), (0, 1), (0, 0), (1, 0)]),
+        _ => None,
+    };
+}
+
+// Grid cells (row from the top, column) covered by a piece at a TBP location, sorted
+fn location_pixels(location: &PieceLocation) -> Option<[(i16, i16); 4]> {
+    let cells = north_cells(&location.piece)?;
+    let mut pixels = [(0, 0); 4];
+    for (idx, (dx, dy)) in cells.into_iter().enumerate() {
+        // clockwise rotations around the center
+        let (dx, dy) = match location.orientation.as_str() {
+            "north" => (dx, dy),
+            "east" => (dy, -dx),
+            "south" => (-dx, -dy),
+            "west" => (-dy, dx),
+            _ => return None,
+        };
+        let x = location.x + dx;
+        let y = location.y + dy;
+        pixels[idx] = (GRID_HEIGHT - 1 - y, x);
+    }
+    pixels.sort();
+    return Some(pixels);
+}
+
+fn to_tbp_board(board: &BitBoard) -> Vec<Vec<Option<String>>> {
+    // TBP rows go from the bottom up, and we don't keep track of which piece filled a cell
+    return (0..TBP_BOARD_HEIGHT)
+        .map(|y| {
+            (0..GRID_WIDTH)
+                .map(|x| {
+                    let is_filled =
+                        y < GRID_HEIGHT as usize && board.is_filled(GRID_HEIGHT - 1 - y as i16, x);
+                    is_filled.then(|| "G".to_string())
+                })
+                .collect()
+        })
+        .collect();
+}
+
+pub struct TbpAgent {
+    process: Child,
+    // as announced by the bot
+    bot_name: String,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    moves: Vec<Move>,
+    // whether the bot is currently playing a game
+    started: bool,
+    // board the bot expects to see after the last placement we played
+    // (if the actual board differs, the bot gets restarted on the actual board)
+    expected_board: Option<BitBoard>,
+}
+
+impl TbpAgent {
+    // `command` is the bot's executable, followed by its arguments
+    pub fn new(command: &str) -> io::Result<Self> {
+        let mut parts = command.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty bot command"))?;
+        let mut process = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = process.stdin.take().unwrap();
+        let stdout = BufReader::new(process.stdout.take().unwrap());
+
+        let mut agent = TbpAgent {
+            process,
+            bot_name: String::new(),
+            stdin,
+            stdout,
+            moves: Vec::new(),
+            started: false,
+            expected_board: None,
+        };
+
+        match agent.receive()? {
+            BotMessage::Info {
+                name,
+                version,
+                author,
+            } => {
+                println!("Playing with TBP bot {} {} by {}", name, version, author);
+                agent.bot_name = format!("{} {}", name, version);
+            }
+            other => return Err(Self::unexpected(other)),
+        }
+        agent.send(&FrontendMessage::Rules {
+            randomizer: "general".to_string(),
+        })?;
+        match agent.receive()? {
+            BotMessage::Ready => (),
+            BotMessage::Error { reason } => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("TBP bot does not support our rules ({})", reason),
+                ))
+            }
+            other => return Err(Self::unexpected(other)),
+        }
+        return Ok(agent);
+    }
+
+    fn unexpected(message: BotMessage) -> io::Error {
+        return io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected message from TBP bot: {:?}", message),
+        );
+    }
+
+    fn send(&mut self, message: &FrontendMessage) -> io::Result<()> {
+        let line = serde_json::to_string(message)?;
+        writeln!(self.stdin, "{}", line)?;
+        return self.stdin.flush();
+    }
+
+    fn receive(&mut self) -> io::Result<BotMessage> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "TBP bot exited",
+            ));
+        }
+        return Ok(serde_json::from_str(&line)?);
+    }
+
+    fn decide_moves(&mut self, observation: &Observation) -> io::Result<Vec<Move>> {
+        // TBP boards do not include the piece being played
+        let mut board = observation.grid.bitboard();
+        board.unset_pixels(&observation.current_shape.get_pixels());
+
+        if self.expected_board == Some(board) {
+            // the bot already knows the current piece and the rest of the preview
+            if let Some(new_piece) = observation.queue.last() {
+                self.send(&FrontendMessage::NewPiece {
+                    piece: piece_name(new_piece),
+                })?;
+            }
+        } else {
+            if self.started {
+                self.send(&FrontendMessage::Stop)?;
+            }
+            let queue = std::iter::once(observation.current_shape)
+                .chain(observation.queue)
+                .map(piece_name)
+                .collect();
+            self.send(&FrontendMessage::Start {
+                hold: observation.hold.map(piece_name),
+                queue,
+                combo: 0,
+                back_to_back: false,
+                board: to_tbp_board(&board),
+            })?;
+            self.started = true;
+        }
+
+        self.send(&FrontendMessage::Suggest)?;
+        let suggested_moves = match self.receive()? {
+            BotMessage::Suggestion { moves } => moves,
+            other => return Err(Self::unexpected(other)),
+        };
+
+        // play the first suggestion that our movement rules can reach
+        let placements = enumerate_placements(&board, observation.current_shape);
+        for suggested_move in suggested_moves {
+            let target = location_pixels(&suggested_move.location);
+            let placement = placements.iter().find(|placement| {
+                let mut pixels = placement.shape.get_pixels();
+                pixels.sort();
+                return Some(pixels) == target;
+            });
+            if let Some(placement) = placement {
+                let mut expected_board = board;
+                expected_board.set_pixels(&placement.shape.get_pixels());
+                expected_board.clear_completed_rows();
+                self.expected_board = Some(expected_board);
+                self.send(&FrontendMessage::Play {
+                    move_: suggested_move,
+                })?;
+                // reversed because we use "pop" to fetch the next move
+                return Ok(placement.moves.iter().rev().cloned().collect());
+            }
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "TBP bot did not suggest any reachable placement",
+        ));
+    }
+}
+
+impl Agent for TbpAgent {
+    fn update_policy(&mut self, observation: &Observation) {
+        match self.decide_moves(observation) {
+            Ok(moves) => self.moves = moves,
+            Err(err) => {
+                eprintln!("TBP bot error: {}", err);
+                // drop the piece where it is, and resynchronize the bot on the next one
+                self.moves = vec![Move::HardDrop];
+                self.expected_board = None;
+            }
+        }
+    }
+
+    fn pop_next_move(&mut self) -> Option<Move> {
+        return self.moves.pop();
+    }
+
+    fn name(&self) -> String {
+        return format!("tbp:{}", self.bot_name);
+    }
+}
+
+impl Drop for TbpAgent {
+    fn drop(&mut self) {
+        // the bot may already be gone, in which case there is nothing left to clean up
+        let _ = self.send(&FrontendMessage::Quit);
+        let _ = self.process.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+
+    const N_PIECES: usize = 20;
+
+    // the example bot only drops its pieces, in their north orientation
+    fn example_bot_command() -> String {
+        return format!(
+            "python3 {}/scripts/tbp_example_bot.py",
+            env!("CARGO_MANIFEST_DIR")
+        );
+    }
+
+    #[test]
+    fn plays_the_example_bot_suggestions() {
+        let mut agent = TbpAgent::new(&example_bot_command()).unwrap();
+        assert_eq!(agent.bot_name, "example 0.1");
+        let mut engine = Engine::new(0);
+        for _ in 0..N_PIECES {
+            let moves = agent.decide_moves(&engine.observation()).unwrap();
+            let expected_board = agent.expected_board;
+            let mut is_locked = false;
+            for move_ in moves.iter().rev() {
+                is_locked = engine.apply_move(move_);
+            }
+            assert!(is_locked);
+            if engine.is_game_over() {
+                break;
+            }
+            // the bot keeps playing on the same board, without being restarted
+            let mut board = engine.grid().bitboard();
+            board.unset_pixels(&engine.current_shape().get_pixels());
+            assert_eq!(Some(board), expected_board);
+        }
+    }
+}
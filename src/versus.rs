@@ -0,0 +1,295 @@
+// Two players side by side, sending garbage to each other when they clear lines.
+// The last one standing wins.
+use std::time::{Duration, Instant};
+
+use macroquad::{
+    camera::{set_camera, set_default_camera, Camera2D},
+    math::Rect,
+    prelude::{is_key_down, KeyCode, RED, WHITE},
+    shapes::draw_rectangle,
+    text::{draw_text, measure_text},
+    window::{next_frame, request_new_screen_size, screen_height, screen_width},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agents::Agent,
+    constants::{BLOCK_SIZE, GRID_HEIGHT, GRID_WIDTH},
+    engine::{Engine, PieceLocked},
+    game::Game,
+    modes::{GameEnd, GameMode},
+    moves::KeyBindings,
+    music::MusicPlayer,
+};
+
+// Lines of garbage sent for each kind of line clear
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AttackTable {
+    // single, double, triple and tetris
+    pub lines: [u32; 4],
+    // T-spin single, double and triple
+    pub t_spins: [u32; 3],
+    // extra line for a tetris or a T-spin right after another one
+    // (pieces that clear no line do not break the chain)
+    pub back_to_back_bonus: u32,
+    // extra lines by number of consecutive line clears before this one
+    // (the last value applies to longer combos)
+    pub combos: Vec<u32>,
+}
+
+impl Default for AttackTable {
+    // from the guideline
+    fn default() -> Self {
+        return AttackTable {
+            lines: [0, 1, 2, 4],
+            t_spins: [2, 4, 6],
+            back_to_back_bonus: 1,
+            combos: vec![0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+        };
+    }
+}
+
+impl AttackTable {
+    // reads a table from a JSON file, where missing fields take their default value
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        return serde_json::from_str(&contents).map_err(|err| format!("{}: {}", path, err));
+    }
+
+    // the table of the file if one is given, the default one otherwise
+    pub fn load_or_default(path: Option<&str>) -> Result<Self, String> {
+        return match path {
+            Some(path) => AttackTable::load(path),
+            None => Ok(AttackTable::default()),
+        };
+    }
+
+    // `combo` counts the line clears in a row before this one
+    pub fn attack(&self, lock: &PieceLocked, combo: usize, is_back_to_back: bool) -> u32 {
+        if lock.lines_cleared == 0 {
+            return 0;
+        }
+        let index = lock.lines_cleared as usize - 1;
+        let mut lines = if lock.is_t_spin {
+            self.t_spins[index.min(self.t_spins.len() - 1)]
+        } else {
+            self.lines[index]
+        };
+        if is_back_to_back {
+            lines += self.back_to_back_bonus;
+        }
+        if let Some(&last) = self.combos.last() {
+            lines += *self.combos.get(combo).unwrap_or(&last);
+        }
+        return lines;
+    }
+}
+
+// tetrises and T-spins, that keep a back-to-back chain going
+fn is_difficult_clear(lock: &PieceLocked) -> bool {
+    return lock.lines_cleared == 4 || (lock.is_t_spin && lock.lines_cleared > 0);
+}
+
+// Garbage exchange of one player
+pub struct VersusState {
+    attack_table: AttackTable,
+    // line clears in a row so far
+    combo: usize,
+    // whether the last line clear was a difficult one
+    back_to_back: bool,
+    // garbage received and not yet added to the grid, by attack, oldest first
+    pending: Vec<u32>,
+    // lines sent and not yet delivered to the opponent
+    outgoing: u32,
+}
+
+impl VersusState {
+    // a pending meter as high as the grid shows this many lines
+    const MAX_METER_LINES: u32 = GRID_HEIGHT as u32;
+
+    pub fn new(attack_table: AttackTable) -> Self {
+        return VersusState {
+            attack_table,
+            combo: 0,
+            back_to_back: false,
+            pending: Vec::new(),
+            outgoing: 0,
+        };
+    }
+
+    pub fn pending_lines(&self) -> u32 {
+        return self.pending.iter().sum();
+    }
+
+    pub fn receive(&mut self, lines: u32) {
+        if lines > 0 {
+            self.pending.push(lines);
+        }
+    }
+
+    pub fn take_outgoing(&mut self) -> u32 {
+        return std::mem::take(&mut self.outgoing);
+    }
+
+    // Line clears cancel the pending garbage before being sent to the opponent.
+    // Otherwise, the pending garbage rises, with one hole column per attack.
+    pub fn on_piece_locked(&mut self, lock: &PieceLocked, engine: &mut Engine) {
+        if lock.lines_cleared == 0 {
+            self.combo = 0;
+            for lines in std::mem::take(&mut self.pending) {
+                let hole = engine.random_garbage_hole();
+                engine.add_garbage(&vec![hole; lines as usize]);
+            }
+            return;
+        }
+        let is_difficult = is_difficult_clear(lock);
+        let is_back_to_back = is_difficult && self.back_to_back;
+        let mut attack = self.attack_table.attack(lock, self.combo, is_back_to_back);
+        self.combo += 1;
+        self.back_to_back = is_difficult;
+
+        while attack > 0 && !self.pending.is_empty() {
+            let cancelled = attack.min(self.pending[0]);
+            attack -= cancelled;
+            self.pending[0] -= cancelled;
+            if self.pending[0] == 0 {
+                self.pending.remove(0);
+            }
+        }
+        self.outgoing += attack;
+    }
+
+    // red bar on the right of the grid, rising from the bottom
+    pub fn draw_meter(&self) {
+        let lines = self.pending_lines().min(Self::MAX_METER_LINES);
+        let height = (lines as i16 * BLOCK_SIZE) as f32;
+        draw_rectangle(
+            (GRID_WIDTH * BLOCK_SIZE) as f32 + 4.,
+            (GRID_HEIGHT * BLOCK_SIZE) as f32 - height,
+            8.,
+            height,
+            RED,
+        );
+    }
+}
+
+// The rules of a versus game: marathon gravity, until the stack tops out
+pub struct VersusMode;
+
+impl GameMode for VersusMode {
+    fn name(&self) -> &'static str {
+        return "versus";
+    }
+
+    fn counts_for_high_scores(&self, _end: GameEnd) -> bool {
+        return false;
+    }
+}
+
+// size of the area drawn by a game, that gets scaled down to half of the window
+pub const FIELD_WIDTH: f32 = 800.;
+pub const FIELD_HEIGHT: f32 = 600.;
+// above the score of each player
+pub const NAME_POSITION: (f32, f32) = (400., 120.);
+
+// draws on the half of the window of the given player, scaled to fit
+pub fn field_camera(player: usize) -> Camera2D {
+    let (width, height) = (screen_width() / 2., screen_height());
+    let scale = f32::min(width / FIELD_WIDTH, height / FIELD_HEIGHT);
+    let mut camera = Camera2D::from_display_rect(Rect::new(0., 0., width / scale, height / scale));
+    camera.viewport = Some((
+        (player as f32 * width) as i32,
+        0,
+        width as i32,
+        height as i32,
+    ));
+    return camera;
+}
+
+pub fn draw_centered(message: &str) {
+    let font_size = 60;
+    let text_dims = measure_text(message, None, font_size, 1.);
+    draw_text(
+        message,
+        (screen_width() - text_dims.width) / 2.,
+        (screen_height() - text_dims.height) / 2.,
+        font_size as f32,
+        WHITE,
+    );
+}
+
+// Plays a match between two players, each a human if there is no bot.
+// Returns the index of the winner, or None on a draw or if the match got quit
+pub async fn play_match(
+    bots: [Option<Box<dyn Agent>>; 2],
+    attack_table: &AttackTable,
+) -> Option<usize> {
+    // two humans can't both use the arrows
+    let key_bindings = if bots.iter().all(|bot| bot.is_none()) {
+        [KeyBindings::WASD_SPACE, KeyBindings::ARROWS_ENTER]
+    } else {
+        [KeyBindings::ARROWS_SPACE, KeyBindings::ARROWS_SPACE]
+    };
+    let mut names = Vec::new();
+    let mut games = Vec::new();
+    for (i, bot) in bots.into_iter().enumerate() {
+        names.push(match &bot {
+            Some(bot) => bot.name(),
+            None => format!("Player {}", i + 1),
+        });
+        let mut game = Game::new(bot, Box::new(VersusMode), 1, false);
+        // the match has its own music
+        game.mute();
+        game.set_key_bindings(key_bindings[i]);
+        game.enable_versus(attack_table.clone());
+        games.push(game);
+    }
+    let music_player = MusicPlayer::new(true);
+    request_new_screen_size(2. * FIELD_WIDTH, FIELD_HEIGHT);
+
+    let winner = loop {
+        for (i, game) in games.iter_mut().enumerate() {
+            set_camera(&field_camera(i));
+            game.update_game();
+            draw_text(&names[i], NAME_POSITION.0, NAME_POSITION.1, 40., WHITE);
+            set_default_camera();
+        }
+        let outgoing = [
+            games[0].take_outgoing_garbage(),
+            games[1].take_outgoing_garbage(),
+        ];
+        games[0].receive_garbage(outgoing[1]);
+        games[1].receive_garbage(outgoing[0]);
+
+        match (games[0].end().is_some(), games[1].end().is_some()) {
+            (true, true) => break None,
+            (true, false) => break Some(1),
+            (false, true) => break Some(0),
+            (false, false) => (),
+        }
+        next_frame().await;
+        if is_key_down(KeyCode::Q) {
+            request_new_screen_size(FIELD_WIDTH, FIELD_HEIGHT);
+            return None;
+        }
+    };
+
+    music_player.play_game_over();
+    let message = match winner {
+        Some(i) => format!("{} wins!", names[i]),
+        None => "Draw!".to_string(),
+    };
+    let start_time = Instant::now();
+    while start_time.elapsed() < Duration::from_secs(3) {
+        for (i, game) in games.iter().enumerate() {
+            set_camera(&field_camera(i));
+            game.draw();
+            set_default_camera();
+        }
+        draw_centered(&message);
+        next_frame().await;
+    }
+    request_new_screen_size(FIELD_WIDTH, FIELD_HEIGHT);
+    return winner;
+}